@@ -0,0 +1,68 @@
+// src/admin.rs
+//
+// Tiny admin API, reachable only through an mTLS route.
+//
+//   GET  /health  -> "ok"
+//   GET  /status  -> bootstrap, isolation and onion PoW summary (JSON)
+//   POST /newnym  -> rotate all isolation tokens and flush the DNS cache
+
+use anyhow::Result;
+use std::sync::Arc;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::{timeout, Duration};
+use tor_rtcompat::Runtime;
+
+use crate::http::{read_head, respond};
+use crate::proxy::ProxyState;
+
+pub async fn handle_admin<R: Runtime, S>(mut client: S, state: Arc<ProxyState<R>>) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut buf = Vec::new();
+
+    loop {
+        let head = match timeout(Duration::from_secs(30), read_head(&mut client, &mut buf)).await {
+            Ok(Ok(Some(head))) => head,
+            Ok(Ok(None)) | Err(_) => return Ok(()),
+            Ok(Err(e)) => {
+                let _ = respond(&mut client, "400 Bad Request", &[], b"", true).await;
+                return Err(e);
+            }
+        };
+
+        let (status, body) = match (head.method.as_str(), head.target.as_str()) {
+            ("GET", "/health") => ("200 OK", "ok\n".to_string()),
+            ("GET", "/status") => ("200 OK", status_json(&state)),
+            ("POST", "/newnym") => {
+                state.newnym();
+                ("200 OK", "{\"newnym\":true}\n".to_string())
+            }
+            (_, "/health" | "/status" | "/newnym") => ("405 Method Not Allowed", String::new()),
+            _ => ("404 Not Found", String::new()),
+        };
+
+        tracing::info!("Admin API: {} {} -> {}", head.method, head.target, status);
+
+        let headers = [("Content-Type", "application/json"), ("Cache-Control", "no-store")];
+        respond(&mut client, status, &headers, body.as_bytes(), true).await?;
+
+        if head.wants_close() {
+            return Ok(());
+        }
+    }
+}
+
+fn status_json<R: Runtime>(state: &ProxyState<R>) -> String {
+    let bootstrap = state.tor.bootstrap_status();
+    format!(
+        "{{\"version\":\"{}\",\"bootstrap_progress\":{:.2},\"ready_for_traffic\":{},\"isolation_keys\":{},\"dns_cache_entries\":{},{}}}\n",
+        env!("CARGO_PKG_VERSION"),
+        bootstrap.as_frac(),
+        bootstrap.ready_for_traffic(),
+        state.isolation_keys(),
+        state.dns_cache.len(),
+        crate::pow::status_fields()
+    )
+}
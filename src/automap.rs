@@ -0,0 +1,142 @@
+// src/automap.rs
+//
+// Virtual addresses for names that cannot be resolved to real ones
+// (AutomapHostsOnResolve). A lookup for `.onion` or a configured suffix
+// hands out an address from a reserved range; a later connect to that
+// address is rewritten back to the original name before it reaches Tor.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Mutex;
+
+use zeroize::Zeroize;
+
+use crate::config::{cidr_contains, Config};
+
+/// Live mappings; the oldest is forgotten when exceeded
+const MAX_MAPPINGS: usize = 65536;
+
+#[derive(Default)]
+struct Mappings {
+    by_name: HashMap<String, (Ipv4Addr, Ipv6Addr)>,
+    by_addr: HashMap<IpAddr, String>,
+    order: VecDeque<String>,
+    next: u64,
+}
+
+pub struct AddressMap {
+    enabled: bool,
+    suffixes: Vec<String>,
+    v4_net: (u32, u8),
+    v6_net: (u128, u8),
+    /// Host offsets usable in both ranges
+    span: u64,
+    capacity: usize,
+    inner: Mutex<Mappings>,
+}
+
+impl AddressMap {
+    pub fn new(cfg: &Config) -> Self {
+        let (v4, v4_prefix) = cfg.virtual_net_v4;
+        let (v6, v6_prefix) = cfg.virtual_net_v6;
+        let v4_host_bits = 32 - v4_prefix as u32;
+        let v6_host_bits = 128 - v6_prefix as u32;
+
+        // Config guarantees at least 16 host bits in each range
+        let span = 1u64 << v4_host_bits.min(v6_host_bits).min(32);
+
+        AddressMap {
+            enabled: cfg.automap_enabled,
+            suffixes: cfg.automap_suffixes.clone(),
+            v4_net: (u32::from(v4) & !((1u64 << v4_host_bits) - 1) as u32, v4_prefix),
+            v6_net: (u128::from(v6) & !((1u128 << v6_host_bits) - 1), v6_prefix),
+            span,
+            capacity: MAX_MAPPINGS.min(span as usize - 2),
+            inner: Mutex::new(Mappings::default()),
+        }
+    }
+
+    /// Whether lookups of this (lowercase, dot-trimmed) name get a virtual address.
+    pub fn should_map(&self, name: &str) -> bool {
+        self.enabled
+            && self
+                .suffixes
+                .iter()
+                .any(|suffix| suffix == "." || name.ends_with(suffix.as_str()))
+    }
+
+    /// Returns the virtual IPv4 and IPv6 addresses for a name, allocating them on first use.
+    pub fn map(&self, name: &str) -> (Ipv4Addr, Ipv6Addr) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(addrs) = inner.by_name.get(name) {
+            return *addrs;
+        }
+
+        if inner.order.len() >= self.capacity {
+            if let Some(mut oldest) = inner.order.pop_front() {
+                if let Some((v4, v6)) = inner.by_name.remove(&oldest) {
+                    inner.by_addr.remove(&IpAddr::V4(v4));
+                    inner.by_addr.remove(&IpAddr::V6(v6));
+                }
+                oldest.zeroize();
+            }
+        }
+
+        let (v4, v6) = loop {
+            let offset = self.next_offset(&mut inner.next);
+            let v4 = Ipv4Addr::from(self.v4_net.0 | offset as u32);
+            let v6 = Ipv6Addr::from(self.v6_net.0 | offset as u128);
+            if !inner.by_addr.contains_key(&IpAddr::V4(v4)) && !inner.by_addr.contains_key(&IpAddr::V6(v6)) {
+                break (v4, v6);
+            }
+        };
+
+        inner.by_name.insert(name.to_string(), (v4, v6));
+        inner.by_addr.insert(IpAddr::V4(v4), name.to_string());
+        inner.by_addr.insert(IpAddr::V6(v6), name.to_string());
+        inner.order.push_back(name.to_string());
+        (v4, v6)
+    }
+
+    /// Original name behind a virtual address, if one is mapped.
+    pub fn name_for(&self, addr: IpAddr) -> Option<String> {
+        if !self.enabled {
+            return None;
+        }
+        self.inner.lock().unwrap().by_addr.get(&addr).cloned()
+    }
+
+    /// Whether an address falls inside either virtual range.
+    pub fn is_virtual(&self, addr: IpAddr) -> bool {
+        let v4_net = IpAddr::V4(Ipv4Addr::from(self.v4_net.0));
+        let v6_net = IpAddr::V6(Ipv6Addr::from(self.v6_net.0));
+        self.enabled && (cidr_contains(v4_net, self.v4_net.1, addr) || cidr_contains(v6_net, self.v6_net.1, addr))
+    }
+
+    /// Replaces a virtual address literal in `host` with its original name.
+    /// Returns false when the address is virtual but nothing is mapped to it
+    /// (stale or guessed), in which case the connection must be refused.
+    pub fn rewrite(&self, host: &mut String) -> bool {
+        let Ok(addr) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() else {
+            return true;
+        };
+        if !self.is_virtual(addr) {
+            return true;
+        }
+
+        match self.name_for(addr) {
+            Some(name) => {
+                host.zeroize();
+                *host = name;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Next host offset valid in both ranges, skipping the all-zero and all-ones hosts.
+    fn next_offset(&self, next: &mut u64) -> u64 {
+        *next = if *next + 1 >= self.span - 1 { 1 } else { *next + 1 };
+        *next
+    }
+}
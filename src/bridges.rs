@@ -0,0 +1,131 @@
+// src/bridges.rs
+//
+// Bridges and pluggable transports for networks that block Tor.
+// Bridge lines come from BRIDGES and BRIDGES_FILE (torrc syntax, with or
+// without the `Bridge` keyword). Each transport a bridge names must be
+// provided by a PLUGGABLE_TRANSPORTS entry, either a managed binary that
+// Arti launches or an unmanaged one already listening on a local port.
+//
+// Managed transports need process creation, so strict-mode hardening keeps
+// RLIMIT_NPROC available when a bridge line uses one. Arti may relaunch a crashed
+// transport at any time, so starting them before hardening would not be
+// enough. Unmanaged transports keep the fork ban in place.
+
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+use arti_client::config::pt::TransportConfigBuilder;
+use arti_client::config::{BridgeConfigBuilder, CfgPath, TorClientConfigBuilder};
+
+use crate::config::{Config, TransportKind};
+
+/// Whether Arti will have to spawn transport binaries: only managed
+/// transports that some bridge line names are ever launched.
+pub fn spawns_transports(cfg: &Config) -> bool {
+    // An unreadable or invalid bridge list stops startup in `configure`
+    let Ok(bridges) = bridge_lines(cfg).and_then(|lines| parse_bridges(&lines)) else { return false };
+    let named = named_transports(&bridges);
+    cfg.transports
+        .iter()
+        .any(|t| matches!(t.kind, TransportKind::Managed { .. }) && t.protocols.iter().any(|p| named.contains(p.as_str())))
+}
+
+/// Adds the configured bridges and transports to the Tor configuration.
+/// Runs before bootstrap so a bad bridge line or missing binary fails fast.
+pub fn configure(cfg: &Config, tor_cfg: &mut TorClientConfigBuilder) -> Result<()> {
+    let lines = bridge_lines(cfg)?;
+    if lines.is_empty() {
+        if !cfg.transports.is_empty() {
+            tracing::warn!("PLUGGABLE_TRANSPORTS set but no bridges configured; transports unused");
+        }
+        return Ok(());
+    }
+    let bridges = parse_bridges(&lines)?;
+    let named = named_transports(&bridges);
+
+    let mut provided = HashSet::new();
+    let mut used = 0;
+    for transport in &cfg.transports {
+        // Unused transports are left out so Arti never launches them
+        if !transport.protocols.iter().any(|p| named.contains(p.as_str())) {
+            tracing::warn!("Pluggable transport '{}' is not used by any bridge; ignored", transport.protocols.join(","));
+            continue;
+        }
+
+        let mut builder = TransportConfigBuilder::default();
+        let protocols = transport
+            .protocols
+            .iter()
+            .map(|p| p.parse().with_context(|| format!("Invalid transport name '{}'", p)))
+            .collect::<Result<Vec<_>>>()?;
+        builder.protocols(protocols);
+
+        match &transport.kind {
+            TransportKind::Managed { path, args } => {
+                let meta = fs::metadata(path).with_context(|| format!("Pluggable transport binary {:?} not found", path))?;
+                if !meta.is_file() || meta.permissions().mode() & 0o111 == 0 {
+                    anyhow::bail!("Pluggable transport binary {:?} is not executable", path);
+                }
+                builder
+                    .path(CfgPath::new_literal(path))
+                    .arguments(args.clone())
+                    .run_on_startup(true); // Surface a broken binary during bootstrap, not on first use
+            }
+            TransportKind::Unmanaged(addr) => {
+                builder.proxy_addr(*addr);
+            }
+        }
+
+        provided.extend(transport.protocols.iter().cloned());
+        tor_cfg.bridges().transports().push(builder);
+        used += 1;
+    }
+
+    for (i, bridge) in bridges.into_iter().enumerate() {
+        // Arti only insists that one bridge be usable; a typo should not leave the rest silently idle
+        if let Some(transport) = bridge_transport(&bridge) {
+            if !provided.contains(transport) {
+                anyhow::bail!("Bridge line {} uses transport '{}' but no PLUGGABLE_TRANSPORTS entry provides it", i + 1, transport);
+            }
+        }
+        tor_cfg.bridges().bridges().push(bridge);
+    }
+
+    tracing::info!("Using {} bridges via {} pluggable transports", lines.len(), used);
+    Ok(())
+}
+
+fn parse_bridges(lines: &[String]) -> Result<Vec<BridgeConfigBuilder>> {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| line.parse().with_context(|| format!("Invalid bridge line {}", i + 1)))
+        .collect()
+}
+
+/// Transport a bridge line names, if it is not a plain relay
+fn bridge_transport(bridge: &BridgeConfigBuilder) -> Option<&str> {
+    bridge.get_transport().filter(|t| !t.is_empty() && *t != "-")
+}
+
+fn named_transports(bridges: &[BridgeConfigBuilder]) -> HashSet<&str> {
+    bridges.iter().filter_map(bridge_transport).collect()
+}
+
+fn bridge_lines(cfg: &Config) -> Result<Vec<String>> {
+    let mut lines = cfg.bridge_lines.clone();
+
+    if let Some(path) = &cfg.bridges_file {
+        let text = fs::read_to_string(path).with_context(|| format!("Cannot read BRIDGES_FILE {:?}", path))?;
+        lines.extend(
+            text.lines()
+                .map(|l| l.split('#').next().unwrap_or_default().trim())
+                .filter(|l| !l.is_empty())
+                .map(String::from),
+        );
+    }
+
+    Ok(lines)
+}
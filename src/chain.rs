@@ -0,0 +1,62 @@
+// src/chain.rs
+//
+// Proxy chains for destinations that block Tor exits. A CHAIN_RULES entry
+// sends matching connects to a second-hop proxy instead: the Tor stream goes
+// to the hop (on the connect's own isolation token), then an HTTP CONNECT or
+// SOCKS5 handshake inside it reaches the real destination. The exit relay
+// only ever sees the hop's address.
+//
+// Hop credentials are read once at startup; a missing file stops startup
+// rather than failing every matching connect later.
+
+use anyhow::{Context, Result};
+
+use crate::config::{ChainRule, Config, UpstreamProxy};
+use crate::policy;
+use crate::tunnel::{self, Credentials, TunnelError};
+
+pub struct Hop {
+    rule: ChainRule,
+    credentials: Option<Credentials>,
+}
+
+pub struct ProxyChains {
+    hops: Vec<Hop>,
+}
+
+impl ProxyChains {
+    pub fn load(cfg: &Config) -> Result<Self> {
+        let mut hops = Vec::new();
+        for rule in &cfg.chain_rules {
+            let credentials = Credentials::read(rule.via.kind, None, rule.credentials.as_deref())
+                .with_context(|| format!("Invalid credentials for chain rule '{}'", rule.matcher.text))?;
+            hops.push(Hop { rule: rule.clone(), credentials });
+        }
+
+        if !hops.is_empty() {
+            tracing::info!("Proxy chains: {} rules loaded", hops.len());
+        }
+        Ok(ProxyChains { hops })
+    }
+
+    /// First hop whose rule covers `host:port`, if any.
+    pub fn hop_for(&self, host: &str, port: u16) -> Option<&Hop> {
+        self.hops.iter().find(|hop| policy::matches(&hop.rule.matcher, host, port))
+    }
+}
+
+impl Hop {
+    /// The proxy to open the Tor stream to
+    pub fn proxy(&self) -> &UpstreamProxy {
+        &self.rule.via
+    }
+
+    /// Asks the hop, over an open Tor stream, for a tunnel to `host:port`.
+    pub async fn open<S>(&self, stream: &mut S, host: &str, port: u16) -> Result<(), TunnelError>
+    where
+        S: futures::AsyncRead + futures::AsyncWrite + Unpin,
+    {
+        tracing::debug!("Chaining {}:{} via {}", host, port, self.rule.via.addr);
+        tunnel::open(stream, self.rule.via.kind, host, port, self.credentials.as_ref()).await
+    }
+}
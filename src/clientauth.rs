@@ -0,0 +1,155 @@
+// src/clientauth.rs
+//
+// Client authorization keys for onion services that run in restricted
+// discovery mode: without the right x25519 key their descriptor cannot be
+// decrypted. ONION_CLIENT_AUTH_DIR may hold keys in either format:
+//
+//   <name>.auth_private                               C Tor (ClientOnionAuthDir):
+//                                                     <address>:descriptor:x25519:<base32 key>
+//   client/<address>/ks_hsc_desc_enc.x25519_private   Arti keystore layout (OpenSSH-encoded)
+//
+// so the directory can be a C Tor auth dir, an Arti keystore, or both. Keys
+// are parsed before Tor bootstraps and inserted into the in-memory keystore.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use arti_client::{HsId, KeystoreSelector, TorClient};
+use data_encoding::BASE32_NOPAD;
+use tor_hscrypto::pk::HsClientDescEncSecretKey;
+use tor_keymgr::ssh_key::private::{KeypairData, PrivateKey};
+use tor_llcrypto::pk::curve25519;
+use tor_rtcompat::Runtime;
+use zeroize::Zeroizing;
+
+use crate::config::Config;
+
+/// Algorithm name Arti uses for x25519 keys in OpenSSH containers
+const ARTI_X25519: &str = "x25519@spec.torproject.org";
+
+/// File name of a descriptor decryption key in Arti's keystore
+const ARTI_KEY_FILE: &str = "ks_hsc_desc_enc.x25519_private";
+
+pub struct ClientKey {
+    hsid: HsId,
+    secret: HsClientDescEncSecretKey,
+}
+
+/// Reads every key in ONION_CLIENT_AUTH_DIR. A malformed file stops startup.
+pub fn load(cfg: &Config) -> Result<Vec<ClientKey>> {
+    let Some(dir) = &cfg.onion_client_auth_dir else { return Ok(Vec::new()) };
+    let mut keys = Vec::new();
+
+    for entry in fs::read_dir(dir).with_context(|| format!("Cannot read ONION_CLIENT_AUTH_DIR {:?}", dir))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "auth_private") {
+            keys.extend(parse_ctor_file(&path).with_context(|| format!("Invalid client auth file {:?}", path))?);
+        }
+    }
+
+    let arti_clients = dir.join("client");
+    if arti_clients.is_dir() {
+        for entry in fs::read_dir(&arti_clients)? {
+            let service_dir = entry?.path();
+            if !service_dir.is_dir() {
+                continue;
+            }
+            // A misnamed key would otherwise be skipped and the service silently unreachable
+            for entry in fs::read_dir(&service_dir)? {
+                let path = entry?.path();
+                if path.extension().is_none_or(|ext| ext != "x25519_private") {
+                    continue;
+                }
+                if path.file_name().is_none_or(|name| name != ARTI_KEY_FILE) {
+                    anyhow::bail!("Unexpected Arti client key {:?} (expected {})", path, ARTI_KEY_FILE);
+                }
+                keys.push(parse_arti_file(&service_dir, &path).with_context(|| format!("Invalid Arti client key {:?}", path))?);
+            }
+        }
+    }
+
+    if keys.is_empty() {
+        tracing::warn!("ONION_CLIENT_AUTH_DIR {:?} holds no client authorization keys", dir);
+    }
+    Ok(keys)
+}
+
+/// Inserts the keys into the client's keystore, one per service.
+pub fn install<R: Runtime>(tor: &TorClient<R>, keys: Vec<ClientKey>) -> Result<()> {
+    let count = keys.len();
+    for ClientKey { hsid, secret } in keys {
+        tor.insert_service_discovery_key(KeystoreSelector::Primary, hsid, secret)
+            .context("Failed to insert onion client authorization key")?;
+    }
+
+    if count > 0 {
+        tracing::info!("Onion client authorization: {} keys loaded", count);
+    }
+    Ok(())
+}
+
+/// C Tor format, one key per line: `<address>:descriptor:x25519:<base32 secret>`
+fn parse_ctor_file(path: &Path) -> Result<Vec<ClientKey>> {
+    let text = Zeroizing::new(fs::read_to_string(path)?);
+    let mut keys = Vec::new();
+
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let mut fields = line.splitn(4, ':');
+        let (Some(address), Some("descriptor"), Some("x25519"), Some(secret)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            anyhow::bail!("Expected `<address>:descriptor:x25519:<key>`");
+        };
+
+        let secret = Zeroizing::new(
+            BASE32_NOPAD
+                .decode(secret.to_ascii_uppercase().as_bytes())
+                .map_err(|_| anyhow::anyhow!("Key is not base32"))?,
+        );
+        keys.push(ClientKey {
+            hsid: parse_hsid(address)?,
+            secret: secret_key(&secret)?,
+        });
+    }
+
+    Ok(keys)
+}
+
+/// Arti format: an unencrypted OpenSSH container under `client/<address>/`
+fn parse_arti_file(service_dir: &Path, path: &Path) -> Result<ClientKey> {
+    let address = service_dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .context("Service directory name is not an onion address")?;
+
+    let text = Zeroizing::new(fs::read_to_string(path)?);
+    let key = PrivateKey::from_openssh(text.as_bytes()).context("Not an OpenSSH private key")?;
+    if key.is_encrypted() {
+        anyhow::bail!("Passphrase-protected Arti keys are not supported");
+    }
+
+    let KeypairData::Other(pair) = key.key_data() else {
+        anyhow::bail!("Not an x25519 key ({})", key.algorithm());
+    };
+    if key.algorithm().as_str() != ARTI_X25519 {
+        anyhow::bail!("Not an x25519 key ({})", key.algorithm());
+    }
+
+    Ok(ClientKey {
+        hsid: parse_hsid(address)?,
+        secret: secret_key(pair.private.as_ref())?,
+    })
+}
+
+fn parse_hsid(address: &str) -> Result<HsId> {
+    let address = address.trim_end_matches(".onion").to_ascii_lowercase();
+    HsId::from_str(&format!("{}.onion", address)).map_err(|_| anyhow::anyhow!("'{}' is not a v3 onion address", address))
+}
+
+fn secret_key(bytes: &[u8]) -> Result<HsClientDescEncSecretKey> {
+    let bytes: Zeroizing<[u8; 32]> =
+        Zeroizing::new(bytes.try_into().map_err(|_| anyhow::anyhow!("x25519 key is not 32 bytes"))?);
+    Ok(HsClientDescEncSecretKey::from(curve25519::StaticSecret::from(*bytes)))
+}
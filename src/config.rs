@@ -1,13 +1,238 @@
 // src/config.rs
 
 use std::env;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::path::PathBuf;
+use data_encoding::BASE64_NOPAD;
 use dotenvy::dotenv;
+use tor_llcrypto::pk::ed25519::Ed25519Identity;
+use tor_llcrypto::pk::rsa::RsaIdentity;
 use tracing::info;
 
+/// What unauthenticated TLS peers are shown instead of SOCKS
+#[derive(Clone, Debug)]
+pub enum Decoy {
+    /// Serve files from a local directory
+    Static(PathBuf),
+    /// Splice the connection to a local web server (host:port)
+    Forward(String),
+}
+
+/// Services reachable behind the TLS front-end
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Service {
+    Socks,
+    HttpConnect,
+    Dns,
+    Admin,
+}
+
+/// Client certificate policy of a route
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// Handshake fails without a valid client certificate
+    Required,
+    /// Unauthenticated peers complete the handshake and are sent to the decoy
+    Optional,
+}
+
+/// One SNI/ALPN dispatch rule for the TLS listener
+#[derive(Clone, Debug)]
+pub struct TlsRoute {
+    pub service: Service,
+    /// Exact name or `*.suffix` wildcard
+    pub sni: Option<String>,
+    pub alpn: Option<String>,
+    pub client_auth: ClientAuth,
+    /// Route-specific trust anchors (defaults to TLS_CLIENT_CA_PATH)
+    pub client_ca: Option<PathBuf>,
+}
+
+/// How blocked names are answered over DNS
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockResponse {
+    NxDomain,
+    /// 0.0.0.0 / :: for A and AAAA, NODATA otherwise
+    Zero,
+}
+
+/// Extra domain lists for one client, keyed by certificate common name
+#[derive(Clone, Debug)]
+pub struct ClientRules {
+    pub client: String,
+    pub allow: Vec<PathBuf>,
+    pub block: Vec<PathBuf>,
+}
+
+/// Verdict of a destination rule
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DestAction {
+    Accept,
+    Reject,
+}
+
+/// One ordered destination rule; every condition present must match
+#[derive(Clone, Debug)]
+pub struct DestRule {
+    pub action: DestAction,
+    /// Name and its subdomains
+    pub suffix: Option<String>,
+    /// Only matches IP-literal destinations
+    pub cidr: Option<(IpAddr, u8)>,
+    pub ports: Option<(u16, u16)>,
+    pub onion: Option<bool>,
+    /// Original text, for log messages
+    pub text: String,
+}
+
+/// One pluggable transport (PLUGGABLE_TRANSPORTS entry)
+#[derive(Clone, Debug)]
+pub struct Transport {
+    /// Transport names it provides, e.g. obfs4, webtunnel
+    pub protocols: Vec<String>,
+    pub kind: TransportKind,
+}
+
+#[derive(Clone, Debug)]
+pub enum TransportKind {
+    /// Binary launched (and relaunched) by Arti; needs process creation
+    Managed { path: PathBuf, args: Vec<String> },
+    /// Already running, reached as a SOCKS proxy on this address
+    Unmanaged(SocketAddr),
+}
+
+/// Proxy given as a URL: the way into the Tor network (UPSTREAM_PROXY), or a
+/// hop after it (CHAIN_RULES)
+#[derive(Clone, Debug)]
+pub struct UpstreamProxy {
+    pub kind: ProxyKind,
+    /// `host:port`, resolved once at startup
+    pub addr: String,
+}
+
+/// Destinations reached through a second proxy after the exit (CHAIN_RULES entry)
+#[derive(Clone, Debug)]
+pub struct ChainRule {
+    /// Conditions, as in DEST_POLICY; the action is unused
+    pub matcher: DestRule,
+    /// Hop proxy, itself reached through Tor
+    pub via: UpstreamProxy,
+    /// `user:password` file for the hop
+    pub credentials: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProxyKind {
+    HttpConnect,
+    Socks5,
+}
+
+/// Vanguard relays pinned in the middle of onion circuits (TOR_VANGUARDS)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Vanguards {
+    Lite,
+    Full,
+}
+
+/// Channel padding against traffic analysis (TOR_PADDING)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Padding {
+    Normal,
+    /// Less padding, for metered links
+    Reduced,
+}
+
+/// Directory cache of a private Tor network (TOR_FALLBACK_DIRS entry)
+#[derive(Clone, Debug)]
+pub struct FallbackDir {
+    pub rsa_identity: RsaIdentity,
+    pub ed_identity: Ed25519Identity,
+    pub orports: Vec<SocketAddr>,
+}
+
+/// C Tor's LongLivedPorts default (FTP, SSH, chat and IRC ports), as Arti uses
+const DEFAULT_LONG_LIVED_PORTS: &[u16] = &[21, 22, 706, 1863, 5050, 5190, 5222, 5223, 6523, 6667, 6697, 8300];
+
+/// One hosted onion service (ONION_SERVICES entry)
+#[derive(Clone, Debug)]
+pub struct OnionService {
+    pub nickname: String,
+    /// Virtual port and the local `host:port` it forwards to
+    pub ports: Vec<(u16, String)>,
+    /// Encrypted PKCS#8 Ed25519 identity key; None generates a fresh key each boot
+    pub key: Option<PathBuf>,
+}
+
 #[derive(Clone, Debug)]
 pub struct Config {
     pub socks_port: u16,
+    /// Accept front-end connections on the TCP port
+    pub frontend_tcp: bool,
+    /// Publish the front-end as a v3 onion service (virtual port = SOCKS port)
+    pub frontend_onion: bool,
+    pub frontend_onion_key: Option<PathBuf>,
+    /// Directory of `<client>.auth` discovery keys (restricted discovery)
+    pub frontend_onion_clients: Option<PathBuf>,
+    pub dot_port: Option<u16>,
+    pub dns_bind: Option<SocketAddr>,
+    pub dns_rate_limit: u32,
+    pub trans_bind: Option<SocketAddr>,
+    pub dns_cache_size: usize,
+    pub dns_cache_min_ttl: u32,
+    pub dns_cache_max_ttl: u32,
+    pub automap_enabled: bool,
+    pub automap_suffixes: Vec<String>,
+    pub virtual_net_v4: (Ipv4Addr, u8),
+    pub virtual_net_v6: (Ipv6Addr, u8),
+    pub domain_blocklists: Vec<PathBuf>,
+    pub domain_allowlists: Vec<PathBuf>,
+    pub domain_block_response: BlockResponse,
+    pub domain_client_rules: Vec<ClientRules>,
+    pub onion_only: bool,
+    pub safe_socks: bool,
+    pub mapaddress_file: Option<PathBuf>,
+    pub dest_rules: Vec<DestRule>,
+    pub dest_log_level: Option<tracing::Level>,
+    pub onion_services: Vec<OnionService>,
+    pub onion_key_passphrase_fd: Option<i32>,
+    pub onion_key_passphrase_file: Option<PathBuf>,
+    /// Restricted-discovery keys for onion services we connect to
+    pub onion_client_auth_dir: Option<PathBuf>,
+    /// Onion connects in flight at once; a backstop on PoW solver threads, not a queue
+    pub onion_max_connects: usize,
+    /// Wall-clock cap on one onion connect, and so on the CPU its PoW solves burn
+    pub onion_pow_max_secs: u64,
+    /// Introduction attempts per onion connect; each retry raises PoW effort
+    pub onion_intro_attempts: u32,
+    /// Bridge lines from BRIDGES; BRIDGES_FILE is read at startup
+    pub bridge_lines: Vec<String>,
+    pub bridges_file: Option<PathBuf>,
+    pub transports: Vec<Transport>,
+    pub upstream_proxy: Option<UpstreamProxy>,
+    /// `user:password` for the upstream proxy
+    pub upstream_proxy_credentials_fd: Option<i32>,
+    pub upstream_proxy_credentials_file: Option<PathBuf>,
+    /// Ordered second-hop rules; the first match wins
+    pub chain_rules: Vec<ChainRule>,
+    /// None leaves Arti's own choice
+    pub tor_vanguards: Option<Vanguards>,
+    /// None leaves Arti's own choice
+    pub tor_padding: Option<Padding>,
+    /// Seconds a circuit takes new streams after its first one
+    pub tor_max_dirtiness_secs: u64,
+    /// Exit ports to keep circuits built for ahead of demand
+    pub tor_preemptive_ports: Vec<u16>,
+    /// Ports whose streams need circuits of Stable relays
+    pub tor_long_lived_ports: Vec<u16>,
+    pub tor_connect_timeout_secs: u64,
+    pub tor_resolve_timeout_secs: u64,
+    /// arti.toml fragment merged over torrust's Tor configuration
+    pub arti_config_file: Option<PathBuf>,
+    /// v3 identities of a private network's authorities; only with --testing-network
+    pub tor_dir_authorities: Vec<RsaIdentity>,
+    pub tor_fallback_dirs: Vec<FallbackDir>,
+    /// Consensus parameter overrides
+    pub tor_net_params: Vec<(String, i32)>,
     pub strict_mode: bool,
     pub chaff_enabled: bool,
     pub auto_isolate_domains: bool,
@@ -16,6 +241,330 @@ pub struct Config {
     pub tls_cert_path: PathBuf,
     pub tls_key_path: PathBuf,
     pub tls_client_ca_path: PathBuf, // NEW: CA cert for mTLS
+    pub tls_crypto_provider: String,
+    pub tls_min_version: String,
+    pub tls_cipher_suites: Vec<String>,
+    pub tls_kx_groups: Vec<String>,
+    pub tls_expiry_warn_days: u64,
+    pub tls_expiry_check_hours: u64,
+    pub tls_pkcs12_path: Option<PathBuf>,
+    pub tls_key_passphrase_fd: Option<i32>,
+    pub tls_key_passphrase_file: Option<PathBuf>,
+    pub decoy: Option<Decoy>,
+    pub tls_routes: Vec<TlsRoute>,
+}
+
+/// Splits a comma-separated env value into trimmed, non-empty items.
+fn list_var(name: &str) -> Vec<String> {
+    env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// `rsa=<hex>,ed=<base64>,orport=host:port[,orport=..]`
+fn parse_fallback_dir(entry: &str) -> FallbackDir {
+    let mut rsa_identity = None;
+    let mut ed_identity = None;
+    let mut orports = Vec::new();
+
+    for opt in entry.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+        match opt.split_once('=') {
+            Some(("rsa", v)) => {
+                rsa_identity = Some(RsaIdentity::from_hex(v).unwrap_or_else(|| panic!("Invalid RSA identity '{}' in '{}'", v, entry)))
+            }
+            Some(("ed", v)) => {
+                let bytes = BASE64_NOPAD.decode(v.trim_end_matches('=').as_bytes()).ok();
+                let bytes: [u8; 32] = bytes
+                    .and_then(|b| b.try_into().ok())
+                    .unwrap_or_else(|| panic!("Invalid Ed25519 identity '{}' in '{}'", v, entry));
+                ed_identity = Some(Ed25519Identity::from(bytes));
+            }
+            Some(("orport", v)) => {
+                orports.push(v.parse().unwrap_or_else(|_| panic!("Invalid ORPort address '{}' in '{}'", v, entry)))
+            }
+            _ => panic!("Invalid fallback directory option '{}' in '{}'", opt, entry),
+        }
+    }
+
+    match (rsa_identity, ed_identity) {
+        (Some(rsa_identity), Some(ed_identity)) if !orports.is_empty() => FallbackDir { rsa_identity, ed_identity, orports },
+        _ => panic!("Fallback directory '{}' needs rsa=, ed= and at least one orport=", entry),
+    }
+}
+
+/// Comma-separated ports; unset keeps the default, empty means none.
+fn port_list_var(name: &str, default: &[u16]) -> Vec<u16> {
+    if env::var(name).is_err() {
+        return default.to_vec();
+    }
+    list_var(name)
+        .iter()
+        .map(|p| match p.parse::<u16>() {
+            Ok(port) if port != 0 => port,
+            _ => panic!("Invalid port '{}' in {}", p, name),
+        })
+        .collect()
+}
+
+/// Whole seconds within `min..=max`.
+fn secs_var(name: &str, default: u64, min: u64, max: u64) -> u64 {
+    let secs = env::var(name)
+        .map(|v| v.parse().unwrap_or_else(|_| panic!("Invalid {} '{}' (expected seconds)", name, v)))
+        .unwrap_or(default);
+    if !(min..=max).contains(&secs) {
+        panic!("{} must be between {} and {} seconds", name, min, max);
+    }
+    secs
+}
+
+/// Parses one `client:allow=path,block=path` entry of DOMAIN_CLIENT_RULES.
+fn parse_client_rules(entry: &str) -> ClientRules {
+    let (client, opts) = entry
+        .split_once(':')
+        .unwrap_or_else(|| panic!("Invalid domain client rule '{}' (expected client:allow=..,block=..)", entry));
+
+    let mut rules = ClientRules {
+        client: client.trim().to_string(),
+        allow: Vec::new(),
+        block: Vec::new(),
+    };
+
+    for opt in opts.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+        match opt.split_once('=') {
+            Some(("allow", v)) => rules.allow.push(PathBuf::from(v)),
+            Some(("block", v)) => rules.block.push(PathBuf::from(v)),
+            _ => panic!("Invalid domain client rule option '{}' in '{}'", opt, entry),
+        }
+    }
+
+    rules
+}
+
+/// Parses one `accept|reject[:key=value,...]` entry of DEST_POLICY.
+fn parse_dest_rule(entry: &str) -> DestRule {
+    let (action, opts) = entry.split_once(':').unwrap_or((entry, ""));
+
+    let action = match action.trim() {
+        "accept" => DestAction::Accept,
+        "reject" => DestAction::Reject,
+        other => panic!("Invalid destination rule action '{}' (expected accept or reject)", other),
+    };
+
+    let mut rule = DestRule {
+        action,
+        suffix: None,
+        cidr: None,
+        ports: None,
+        onion: None,
+        text: entry.to_string(),
+    };
+
+    for opt in opts.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+        match opt.split_once('=') {
+            Some(("suffix", v)) => rule.suffix = Some(v.trim_start_matches('.').to_ascii_lowercase()),
+            Some(("cidr", v)) => {
+                rule.cidr = Some(parse_cidr(v).unwrap_or_else(|| panic!("Invalid CIDR '{}' in '{}'", v, entry)))
+            }
+            Some(("ports", v)) => {
+                let (lo, hi) = v.split_once('-').unwrap_or((v, v));
+                match (lo.parse::<u16>(), hi.parse::<u16>()) {
+                    (Ok(lo), Ok(hi)) if lo <= hi => rule.ports = Some((lo, hi)),
+                    _ => panic!("Invalid port range '{}' in '{}'", v, entry),
+                }
+            }
+            Some(("onion", "true")) => rule.onion = Some(true),
+            Some(("onion", "false")) => rule.onion = Some(false),
+            _ => panic!("Invalid destination rule option '{}' in '{}'", opt, entry),
+        }
+    }
+
+    rule
+}
+
+/// Parses one `nickname:port=host:port,...[,key=path]` entry of ONION_SERVICES.
+fn parse_onion_service(entry: &str) -> OnionService {
+    let (nickname, opts) = entry
+        .split_once(':')
+        .unwrap_or_else(|| panic!("Invalid onion service '{}' (expected nickname:port=host:port,..)", entry));
+
+    let mut service = OnionService {
+        nickname: nickname.trim().to_string(),
+        ports: Vec::new(),
+        key: None,
+    };
+
+    for opt in opts.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+        match opt.split_once('=') {
+            Some(("key", v)) => service.key = Some(PathBuf::from(v)),
+            Some((port, target)) => {
+                let port = port
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Invalid onion service option '{}' in '{}'", opt, entry));
+                if target.rsplit_once(':').and_then(|(_, p)| p.parse::<u16>().ok()).is_none() {
+                    panic!("Invalid onion service target '{}' in '{}' (expected host:port)", target, entry);
+                }
+                service.ports.push((port, target.to_string()));
+            }
+            None => panic!("Invalid onion service option '{}' in '{}'", opt, entry),
+        }
+    }
+
+    if service.ports.is_empty() {
+        panic!("Onion service '{}' has no ports", service.nickname);
+    }
+    service
+}
+
+/// `obfs4,webtunnel=/usr/bin/lyrebird [args..]` (managed) or `obfs4=127.0.0.1:4444` (unmanaged)
+fn parse_transport(entry: &str) -> Transport {
+    let (protocols, target) = entry
+        .split_once('=')
+        .unwrap_or_else(|| panic!("Invalid pluggable transport '{}' (expected name=/path/to/binary or name=host:port)", entry));
+
+    let protocols: Vec<String> = protocols.split(',').map(str::trim).filter(|p| !p.is_empty()).map(String::from).collect();
+    if protocols.is_empty() {
+        panic!("Pluggable transport '{}' names no protocols", entry);
+    }
+
+    let mut words = target.split_whitespace();
+    let first = words.next().unwrap_or_else(|| panic!("Pluggable transport '{}' has no binary or address", entry));
+
+    let kind = match first.parse::<SocketAddr>() {
+        Ok(addr) => {
+            if words.next().is_some() {
+                panic!("Pluggable transport '{}': arguments only apply to managed transports", entry);
+            }
+            TransportKind::Unmanaged(addr)
+        }
+        Err(_) if first.starts_with('/') => TransportKind::Managed {
+            path: PathBuf::from(first),
+            args: words.map(String::from).collect(),
+        },
+        Err(_) => panic!("Pluggable transport '{}': '{}' is neither an absolute path nor host:port", entry, first),
+    };
+
+    Transport { protocols, kind }
+}
+
+/// `http://host:port` (HTTP CONNECT) or `socks5://host:port`; `var` names the setting in errors.
+fn parse_proxy_url(value: &str, var: &str) -> UpstreamProxy {
+    let (scheme, addr) = value
+        .split_once("://")
+        .unwrap_or_else(|| panic!("Invalid {} '{}' (expected http://host:port or socks5://host:port)", var, value));
+
+    let kind = match scheme {
+        "http" => ProxyKind::HttpConnect,
+        "socks5" | "socks5h" => ProxyKind::Socks5,
+        _ => panic!("Unsupported {} scheme '{}' (expected http or socks5)", var, scheme),
+    };
+
+    let addr = addr.trim_end_matches('/');
+    if addr.contains('@') {
+        panic!("{} must not embed credentials; read them from a file instead", var);
+    }
+    if addr.rsplit_once(':').and_then(|(_, p)| p.parse::<u16>().ok()).is_none() {
+        panic!("Invalid {} address '{}' (expected host:port)", var, addr);
+    }
+
+    UpstreamProxy { kind, addr: addr.to_string() }
+}
+
+/// `via=socks5://host:port[,credentials=/path][,suffix=..,cidr=..,ports=..,onion=..]`
+fn parse_chain_rule(entry: &str) -> ChainRule {
+    let mut via = None;
+    let mut credentials = None;
+    let mut conditions = Vec::new();
+
+    for opt in entry.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+        match opt.split_once('=') {
+            Some(("via", v)) => via = Some(parse_proxy_url(v, "CHAIN_RULES via")),
+            Some(("credentials", v)) => credentials = Some(PathBuf::from(v)),
+            _ => conditions.push(opt),
+        }
+    }
+
+    let via = via.unwrap_or_else(|| panic!("Chain rule '{}' has no via=<proxy URL>", entry));
+    let mut matcher = parse_dest_rule(&format!("accept:{}", conditions.join(",")));
+    matcher.text = entry.to_string();
+
+    ChainRule { matcher, via, credentials }
+}
+
+/// Parses `addr/prefix`; a bare address is a single host.
+pub fn parse_cidr(value: &str) -> Option<(IpAddr, u8)> {
+    let (addr, prefix) = match value.split_once('/') {
+        Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, Some(prefix.parse::<u8>().ok()?)),
+        None => (value.parse::<IpAddr>().ok()?, None),
+    };
+    let width = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = prefix.unwrap_or(width);
+    (prefix <= width).then_some((addr, prefix))
+}
+
+/// Whether `addr` lies in `net/prefix`. IPv4-mapped IPv6 literals are
+/// compared as IPv4 so they cannot dodge an IPv4 rule.
+pub fn cidr_contains(net: IpAddr, prefix: u8, addr: IpAddr) -> bool {
+    let (addr, net, width) = match (addr, net) {
+        (IpAddr::V4(a), IpAddr::V4(n)) => (u32::from(a) as u128, u32::from(n) as u128, 32),
+        (IpAddr::V6(a), IpAddr::V6(n)) => (u128::from(a), u128::from(n), 128),
+        (IpAddr::V6(a), IpAddr::V4(n)) => match a.to_ipv4_mapped() {
+            Some(a) => (u32::from(a) as u128, u32::from(n) as u128, 32),
+            None => return false,
+        },
+        (IpAddr::V4(_), IpAddr::V6(_)) => return false,
+    };
+
+    let host_bits = (width - prefix) as u32;
+    host_bits == width as u32 || (addr >> host_bits) == (net >> host_bits)
+}
+
+/// Plaintext listeners must never face the internet: loopback, RFC 1918,
+/// CGNAT (VPN overlays) or IPv6 ULA only.
+fn is_local_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            v4.is_loopback() || v4.is_private() || (a == 100 && (64..128).contains(&b))
+        }
+        IpAddr::V6(v6) => v6.is_loopback() || (v6.segments()[0] & 0xfe00) == 0xfc00,
+    }
+}
+
+/// Parses one `service[:key=value,...]` entry of TLS_ROUTES.
+fn parse_route(entry: &str, default_auth: ClientAuth) -> TlsRoute {
+    let (service, opts) = entry.split_once(':').unwrap_or((entry, ""));
+
+    let service = match service.trim() {
+        "socks" => Service::Socks,
+        "http-connect" => Service::HttpConnect,
+        "dns" => Service::Dns,
+        "admin" => Service::Admin,
+        other => panic!("Invalid TLS route service '{}' (expected socks, http-connect, dns or admin)", other),
+    };
+
+    let mut route = TlsRoute {
+        service,
+        sni: None,
+        alpn: None,
+        client_auth: default_auth,
+        client_ca: None,
+    };
+
+    for opt in opts.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+        match opt.split_once('=') {
+            Some(("sni", v)) => route.sni = Some(v.to_ascii_lowercase()),
+            Some(("alpn", v)) => route.alpn = Some(v.to_string()),
+            Some(("client_auth", "required")) => route.client_auth = ClientAuth::Required,
+            Some(("client_auth", "optional")) => route.client_auth = ClientAuth::Optional,
+            Some(("ca", v)) => route.client_ca = Some(PathBuf::from(v)),
+            _ => panic!("Invalid TLS route option '{}' in '{}'", opt, entry),
+        }
+    }
+
+    route
 }
 
 pub fn load() -> Config {
@@ -26,6 +575,257 @@ pub fn load() -> Config {
         .parse()
         .expect("Invalid SOCKS port");
 
+    // Where the TLS front-end is reachable: a TCP port, an onion service, or both
+    let (frontend_tcp, frontend_onion) = match env::var("FRONTEND_LISTEN").unwrap_or_default().as_str() {
+        "" | "tcp" => (true, false),
+        "onion" => (false, true),
+        "both" => (true, true),
+        other => panic!("Invalid FRONTEND_LISTEN '{}' (expected tcp, onion or both)", other),
+    };
+
+    let frontend_onion_key = env::var("FRONTEND_ONION_KEY").ok().filter(|v| !v.is_empty()).map(PathBuf::from);
+    let frontend_onion_clients = env::var("FRONTEND_ONION_CLIENTS").ok().filter(|v| !v.is_empty()).map(PathBuf::from);
+
+    // DNS-over-TLS listener; 0 disables it
+    let dot_port = match env::var("COMMON_DOT_PROXY_PORT")
+        .unwrap_or_else(|_| "853".to_string())
+        .parse()
+        .expect("Invalid DoT port")
+    {
+        0 => None,
+        port => Some(port),
+    };
+
+    // Opt-in plain DNS (UDP+TCP) for local stub resolvers
+    let dns_port: u16 = env::var("COMMON_DNS_PROXY_PORT")
+        .unwrap_or_else(|_| "5353".to_string())
+        .parse()
+        .expect("Invalid DNS port");
+
+    let dns_bind = env::var("DNS_BIND").ok().filter(|v| !v.is_empty()).map(|v| {
+        let ip: IpAddr = v.parse().expect("Invalid DNS_BIND address");
+        if !is_local_address(ip) {
+            panic!("DNS_BIND {} is not a loopback or private address; refusing to expose plain DNS", ip);
+        }
+        SocketAddr::new(ip, dns_port)
+    });
+
+    // Opt-in transparent proxy for REDIRECTed traffic (plaintext, local only)
+    let trans_port: u16 = env::var("COMMON_TRANS_PROXY_PORT")
+        .unwrap_or_else(|_| "9040".to_string())
+        .parse()
+        .expect("Invalid transparent proxy port");
+
+    let trans_bind = env::var("TRANS_BIND").ok().filter(|v| !v.is_empty()).map(|v| {
+        let ip: IpAddr = v.parse().expect("Invalid TRANS_BIND address");
+        if !is_local_address(ip) {
+            panic!("TRANS_BIND {} is not a loopback or private address; refusing to expose the transparent proxy", ip);
+        }
+        SocketAddr::new(ip, trans_port)
+    });
+
+    let dns_rate_limit = env::var("DNS_RATE_LIMIT")
+        .unwrap_or_else(|_| "20".to_string())
+        .parse()
+        .expect("Invalid DNS rate limit");
+
+    // Resolver cache shared by DNS and SOCKS RESOLVE; 0 entries disables it
+    let dns_cache_size = env::var("DNS_CACHE_SIZE")
+        .unwrap_or_else(|_| "1024".to_string())
+        .parse()
+        .expect("Invalid DNS cache size");
+
+    let dns_cache_min_ttl: u32 = env::var("DNS_CACHE_MIN_TTL")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .expect("Invalid DNS cache minimum TTL");
+
+    let dns_cache_max_ttl: u32 = env::var("DNS_CACHE_MAX_TTL")
+        .unwrap_or_else(|_| "600".to_string())
+        .parse()
+        .expect("Invalid DNS cache maximum TTL");
+
+    if dns_cache_min_ttl > dns_cache_max_ttl {
+        panic!("DNS_CACHE_MIN_TTL ({}) exceeds DNS_CACHE_MAX_TTL ({})", dns_cache_min_ttl, dns_cache_max_ttl);
+    }
+
+    // Virtual addresses for .onion (and other unresolvable) names
+    let automap_enabled = env::var("AUTOMAP_HOSTS_ON_RESOLVE").unwrap_or_default() == "1";
+    let mut automap_suffixes: Vec<String> = list_var("AUTOMAP_HOSTS_SUFFIXES")
+        .into_iter()
+        .map(|s| {
+            let s = s.to_ascii_lowercase();
+            if s.starts_with('.') { s } else { format!(".{}", s) }
+        })
+        .collect();
+    if automap_suffixes.is_empty() {
+        automap_suffixes.push(".onion".to_string());
+    }
+
+    let virtual_net_v4 = match parse_cidr(&env::var("VIRTUAL_ADDR_NETWORK_IPV4").unwrap_or_else(|_| "10.192.0.0/10".to_string())) {
+        Some((IpAddr::V4(addr), prefix)) if prefix <= 16 => (addr, prefix),
+        _ => panic!("Invalid VIRTUAL_ADDR_NETWORK_IPV4 (expected an IPv4 network of /16 or larger)"),
+    };
+
+    let virtual_net_v6 = match parse_cidr(&env::var("VIRTUAL_ADDR_NETWORK_IPV6").unwrap_or_else(|_| "fc00::/7".to_string())) {
+        Some((IpAddr::V6(addr), prefix)) if prefix <= 112 => (addr, prefix),
+        _ => panic!("Invalid VIRTUAL_ADDR_NETWORK_IPV6 (expected an IPv6 network of /112 or larger)"),
+    };
+
+    // Domain policy (blocklists, allowlists, per-client overrides)
+    let domain_blocklists = list_var("DOMAIN_BLOCKLISTS").into_iter().map(PathBuf::from).collect();
+    let domain_allowlists = list_var("DOMAIN_ALLOWLISTS").into_iter().map(PathBuf::from).collect();
+
+    let domain_block_response = match env::var("DOMAIN_BLOCK_RESPONSE").unwrap_or_default().as_str() {
+        "" | "nxdomain" => BlockResponse::NxDomain,
+        "zero" => BlockResponse::Zero,
+        other => panic!("Invalid DOMAIN_BLOCK_RESPONSE '{}' (expected nxdomain or zero)", other),
+    };
+
+    let domain_client_rules = env::var("DOMAIN_CLIENT_RULES")
+        .unwrap_or_default()
+        .split(';')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(parse_client_rules)
+        .collect();
+
+    // Destination policy (ordered, first match wins; no match accepts)
+    let onion_only = env::var("ONION_ONLY").unwrap_or_default() == "1";
+    let safe_socks = env::var("SAFE_SOCKS").unwrap_or_default() == "1";
+
+    // Destination rewrites (reloaded on SIGHUP)
+    let mapaddress_file = env::var("MAPADDRESS_FILE").ok().filter(|v| !v.is_empty()).map(PathBuf::from);
+    let dest_rules = env::var("DEST_POLICY")
+        .unwrap_or_default()
+        .split(';')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(parse_dest_rule)
+        .collect();
+
+    let dest_log_level = match env::var("DEST_POLICY_LOG_LEVEL").unwrap_or_else(|_| "debug".to_string()).as_str() {
+        "off" => None,
+        level => Some(level.parse().unwrap_or_else(|_| panic!("Invalid DEST_POLICY_LOG_LEVEL '{}'", level))),
+    };
+
+    // Hosted onion services; keys are ephemeral unless an encrypted key file is given
+    let onion_services = env::var("ONION_SERVICES")
+        .unwrap_or_default()
+        .split(';')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(parse_onion_service)
+        .collect();
+
+    let onion_key_passphrase_fd = env::var("ONION_KEY_PASSPHRASE_FD")
+        .ok()
+        .map(|v| v.parse().expect("Invalid onion key passphrase fd"));
+
+    let onion_key_passphrase_file = env::var("ONION_KEY_PASSPHRASE_FILE").ok().map(PathBuf::from);
+
+    let onion_client_auth_dir = env::var("ONION_CLIENT_AUTH_DIR").ok().filter(|v| !v.is_empty()).map(PathBuf::from);
+
+    let onion_max_connects: usize = env::var("ONION_MAX_CONNECTS")
+        .ok()
+        .map(|v| v.parse().expect("Invalid ONION_MAX_CONNECTS"))
+        .unwrap_or(64);
+    if !(1..=4096).contains(&onion_max_connects) {
+        panic!("ONION_MAX_CONNECTS must be between 1 and 4096");
+    }
+
+    let onion_pow_max_secs = secs_var("ONION_POW_MAX_SECS", 120, 10, 3600);
+
+    let onion_intro_attempts: u32 = env::var("ONION_INTRO_ATTEMPTS")
+        .ok()
+        .map(|v| v.parse().expect("Invalid ONION_INTRO_ATTEMPTS"))
+        .unwrap_or(6);
+    if !(1..=32).contains(&onion_intro_attempts) {
+        panic!("ONION_INTRO_ATTEMPTS must be between 1 and 32");
+    }
+
+    let bridge_lines: Vec<String> = env::var("BRIDGES")
+        .unwrap_or_default()
+        .split(';')
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect();
+    let bridges_file = env::var("BRIDGES_FILE").ok().filter(|v| !v.is_empty()).map(PathBuf::from);
+
+    let transports: Vec<Transport> = env::var("PLUGGABLE_TRANSPORTS")
+        .unwrap_or_default()
+        .split(';')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(parse_transport)
+        .collect();
+
+    let upstream_proxy = env::var("UPSTREAM_PROXY").ok().filter(|v| !v.is_empty()).map(|v| parse_proxy_url(&v, "UPSTREAM_PROXY"));
+
+    // Managed transport binaries dial bridges themselves, past the proxy
+    if upstream_proxy.is_some() && transports.iter().any(|t| matches!(t.kind, TransportKind::Managed { .. })) {
+        panic!("UPSTREAM_PROXY cannot be combined with managed pluggable transports; run them unmanaged behind the proxy");
+    }
+
+    let upstream_proxy_credentials_fd = env::var("UPSTREAM_PROXY_CREDENTIALS_FD")
+        .ok()
+        .map(|v| v.parse().expect("Invalid upstream proxy credentials fd"));
+    let upstream_proxy_credentials_file = env::var("UPSTREAM_PROXY_CREDENTIALS_FILE").ok().filter(|v| !v.is_empty()).map(PathBuf::from);
+
+    let chain_rules: Vec<ChainRule> = env::var("CHAIN_RULES")
+        .unwrap_or_default()
+        .split(';')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(parse_chain_rule)
+        .collect();
+
+    // Circuit and path tuning; the defaults are Arti's own
+    let tor_vanguards = match env::var("TOR_VANGUARDS").unwrap_or_default().as_str() {
+        "" => None,
+        "lite" => Some(Vanguards::Lite),
+        "full" => Some(Vanguards::Full),
+        other => panic!("Invalid TOR_VANGUARDS '{}' (expected lite or full)", other),
+    };
+    let tor_padding = match env::var("TOR_PADDING").unwrap_or_default().as_str() {
+        "" => None,
+        "normal" => Some(Padding::Normal),
+        "reduced" => Some(Padding::Reduced),
+        other => panic!("Invalid TOR_PADDING '{}' (expected normal or reduced)", other),
+    };
+    let tor_max_dirtiness_secs = secs_var("TOR_MAX_DIRTINESS_SECS", 600, 10, 86400);
+    let tor_preemptive_ports = port_list_var("TOR_PREEMPTIVE_PORTS", &[80, 443]);
+    let tor_long_lived_ports = port_list_var("TOR_LONG_LIVED_PORTS", DEFAULT_LONG_LIVED_PORTS);
+    let tor_connect_timeout_secs = secs_var("TOR_CONNECT_TIMEOUT_SECS", 10, 1, 300);
+    let tor_resolve_timeout_secs = secs_var("TOR_RESOLVE_TIMEOUT_SECS", 10, 1, 300);
+    let arti_config_file = env::var("ARTI_CONFIG_FILE").ok().filter(|v| !v.is_empty()).map(PathBuf::from);
+
+    // Private test networks (chutney and the like); main refuses these without --testing-network
+    let tor_dir_authorities: Vec<RsaIdentity> = list_var("TOR_DIR_AUTHORITIES")
+        .iter()
+        .map(|id| RsaIdentity::from_hex(id).unwrap_or_else(|| panic!("Invalid authority v3 identity '{}'", id)))
+        .collect();
+    let tor_fallback_dirs: Vec<FallbackDir> = env::var("TOR_FALLBACK_DIRS")
+        .unwrap_or_default()
+        .split(';')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(parse_fallback_dir)
+        .collect();
+    let tor_net_params: Vec<(String, i32)> = list_var("TOR_NET_PARAMS")
+        .iter()
+        .map(|p| match p.split_once('=').map(|(k, v)| (k.trim(), v.trim().parse::<i32>())) {
+            Some((name, Ok(value))) if !name.is_empty() => (name.to_string(), value),
+            _ => panic!("Invalid TOR_NET_PARAMS entry '{}' (expected name=integer)", p),
+        })
+        .collect();
+
+    // Arti refuses custom authorities with the public fallback list; say so in our own terms
+    if !tor_dir_authorities.is_empty() && tor_fallback_dirs.is_empty() {
+        panic!("TOR_DIR_AUTHORITIES needs TOR_FALLBACK_DIRS for the same network");
+    }
+
     let strict_mode = env::var("SECMEM_STRICT").unwrap_or_default() == "1";
     let chaff_enabled = env::var("TORGO_ENABLE_CHAFF").unwrap_or_default() == "1";
     let auto_isolate_domains = env::var("AUTO_ISOLATE_DOMAINS").unwrap_or_default() == "1";
@@ -51,8 +851,116 @@ pub fn load() -> Config {
         .map(PathBuf::from)
         .unwrap_or_else(|_| PathBuf::from("/etc/torrust/certs/ca.crt"));
 
+    // TLS policy (validated against the provider when the listener is built)
+    let tls_crypto_provider = env::var("TLS_CRYPTO_PROVIDER").unwrap_or_else(|_| "ring".to_string());
+    let tls_min_version = env::var("TLS_MIN_VERSION").unwrap_or_else(|_| "1.2".to_string());
+    let tls_cipher_suites = list_var("TLS_CIPHER_SUITES");
+    let tls_kx_groups = list_var("TLS_KX_GROUPS");
+
+    let tls_expiry_warn_days: u64 = env::var("TLS_EXPIRY_WARN_DAYS")
+        .unwrap_or_else(|_| "30".to_string())
+        .parse()
+        .expect("Invalid TLS expiry warning window");
+    if tls_expiry_warn_days > 3650 {
+        panic!("TLS_EXPIRY_WARN_DAYS must be at most 3650");
+    }
+
+    let tls_expiry_check_hours: u64 = env::var("TLS_EXPIRY_CHECK_HOURS")
+        .unwrap_or_else(|_| "12".to_string())
+        .parse()
+        .expect("Invalid TLS expiry check interval");
+    if !(1..=8760).contains(&tls_expiry_check_hours) {
+        panic!("TLS_EXPIRY_CHECK_HOURS must be between 1 and 8760");
+    }
+
+    // Optional server identity bundle and passphrase source for encrypted keys
+    let tls_pkcs12_path = env::var("TLS_PKCS12_PATH").ok().map(PathBuf::from);
+
+    let tls_key_passphrase_fd = env::var("TLS_KEY_PASSPHRASE_FD")
+        .ok()
+        .map(|v| v.parse().expect("Invalid passphrase fd"));
+
+    let tls_key_passphrase_file = env::var("TLS_KEY_PASSPHRASE_FILE").ok().map(PathBuf::from);
+
+    // Probe resistance: client certs become optional at the handshake
+    let decoy = match env::var("DECOY_MODE").unwrap_or_default().as_str() {
+        "" | "off" => None,
+        "static" => Some(Decoy::Static(
+            env::var("DECOY_STATIC_ROOT")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| PathBuf::from("/etc/torrust/decoy")),
+        )),
+        "forward" => Some(Decoy::Forward(
+            env::var("DECOY_BACKEND").expect("DECOY_BACKEND is required when DECOY_MODE=forward"),
+        )),
+        other => panic!("Invalid DECOY_MODE '{}' (expected off, static or forward)", other),
+    };
+
+    // SNI/ALPN dispatch on the single TLS port (first match wins)
+    let default_auth = if decoy.is_some() { ClientAuth::Optional } else { ClientAuth::Required };
+    let mut tls_routes: Vec<TlsRoute> = env::var("TLS_ROUTES")
+        .unwrap_or_default()
+        .split(';')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(|e| parse_route(e, default_auth))
+        .collect();
+
+    if tls_routes.is_empty() {
+        tls_routes.push(parse_route("socks", default_auth));
+    }
+
     let cfg = Config {
         socks_port,
+        frontend_tcp,
+        frontend_onion,
+        frontend_onion_key,
+        frontend_onion_clients,
+        dot_port,
+        dns_bind,
+        dns_rate_limit,
+        trans_bind,
+        dns_cache_size,
+        dns_cache_min_ttl,
+        dns_cache_max_ttl,
+        automap_enabled,
+        automap_suffixes,
+        virtual_net_v4,
+        virtual_net_v6,
+        domain_blocklists,
+        domain_allowlists,
+        domain_block_response,
+        domain_client_rules,
+        onion_only,
+        safe_socks,
+        mapaddress_file,
+        dest_rules,
+        dest_log_level,
+        onion_services,
+        onion_key_passphrase_fd,
+        onion_key_passphrase_file,
+        onion_client_auth_dir,
+        onion_max_connects,
+        onion_pow_max_secs,
+        onion_intro_attempts,
+        bridge_lines,
+        bridges_file,
+        transports,
+        upstream_proxy,
+        upstream_proxy_credentials_fd,
+        upstream_proxy_credentials_file,
+        chain_rules,
+        tor_vanguards,
+        tor_padding,
+        tor_max_dirtiness_secs,
+        tor_preemptive_ports,
+        tor_long_lived_ports,
+        tor_connect_timeout_secs,
+        tor_resolve_timeout_secs,
+        arti_config_file,
+        tor_dir_authorities,
+        tor_fallback_dirs,
+        tor_net_params,
         strict_mode,
         chaff_enabled,
         auto_isolate_domains,
@@ -61,13 +969,40 @@ pub fn load() -> Config {
         tls_cert_path,
         tls_key_path,
         tls_client_ca_path,
+        tls_crypto_provider,
+        tls_min_version,
+        tls_cipher_suites,
+        tls_kx_groups,
+        tls_expiry_warn_days,
+        tls_expiry_check_hours,
+        tls_pkcs12_path,
+        tls_key_passphrase_fd,
+        tls_key_passphrase_file,
+        decoy,
+        tls_routes,
     };
 
     info!(
-        "Config loaded: SOCKS={} (mTLS), Strict={}, Auto-Isolate={}",
+        "Config loaded: SOCKS={} (mTLS, tcp={}, onion={}), DoT={:?}, DNS={:?}, Strict={}, Auto-Isolate={}, Decoy={}, Onion-Only={}",
         cfg.socks_port,
+        cfg.frontend_tcp,
+        cfg.frontend_onion,
+        cfg.dot_port,
+        cfg.dns_bind,
         cfg.strict_mode,
-        cfg.auto_isolate_domains
+        cfg.auto_isolate_domains,
+        cfg.decoy.is_some(),
+        cfg.onion_only
+    );
+    info!(
+        "Tor tuning: Vanguards={}, Padding={}, Dirtiness={}s, Preemptive-Ports={:?}, Long-Lived-Ports={:?}, Connect-Timeout={}s, Resolve-Timeout={}s",
+        cfg.tor_vanguards.map_or_else(|| "auto".to_string(), |v| format!("{:?}", v)),
+        cfg.tor_padding.map_or_else(|| "auto".to_string(), |p| format!("{:?}", p)),
+        cfg.tor_max_dirtiness_secs,
+        cfg.tor_preemptive_ports,
+        cfg.tor_long_lived_ports,
+        cfg.tor_connect_timeout_secs,
+        cfg.tor_resolve_timeout_secs
     );
 
     cfg
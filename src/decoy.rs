@@ -0,0 +1,144 @@
+// src/decoy.rs
+//
+// Probe resistance for the mTLS listener.
+// Peers that complete TLS without a client certificate never see SOCKS;
+// they get an ordinary-looking HTTPS site instead of a dropped connection.
+//
+// Kept deliberately small: GET/HEAD of static files, or a blind TCP forward.
+
+use anyhow::{Context, Result};
+use std::path::{Component, Path, PathBuf};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration};
+
+use crate::config::Decoy;
+use crate::http::{read_head, respond};
+
+/// Idle time allowed between requests on a kept-alive connection
+const IDLE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Entry point for unauthenticated TLS peers
+pub async fn serve<S>(mut stream: S, decoy: &Decoy) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    match decoy {
+        Decoy::Static(root) => serve_static(&mut stream, root).await,
+        Decoy::Forward(backend) => {
+            let mut upstream = TcpStream::connect(backend)
+                .await
+                .with_context(|| format!("Decoy backend {} unreachable", backend))?;
+            let _ = upstream.set_nodelay(true);
+            tokio::io::copy_bidirectional(&mut stream, &mut upstream).await?;
+            Ok(())
+        }
+    }
+}
+
+async fn serve_static<S>(stream: &mut S, root: &Path) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut buf = Vec::with_capacity(1024);
+
+    loop {
+        let head = match timeout(IDLE_TIMEOUT, read_head(stream, &mut buf)).await {
+            Ok(Ok(Some(head))) => head,
+            Ok(Ok(None)) | Err(_) => return Ok(()),
+            Ok(Err(_)) => return respond_page(stream, "400 Bad Request", None, false, true).await,
+        };
+
+        let close = head.wants_close();
+        let head_only = head.method == "HEAD";
+        if head.method != "GET" && !head_only {
+            return respond_page(stream, "405 Not Allowed", None, false, true).await;
+        }
+
+        match resolve_path(root, &head.target) {
+            Some(path) => match tokio::fs::read(&path).await {
+                Ok(body) => respond_page(stream, "200 OK", Some((content_type(&path), &body)), !close, !head_only).await?,
+                Err(_) => respond_page(stream, "404 Not Found", None, !close, !head_only).await?,
+            },
+            None => return respond_page(stream, "400 Bad Request", None, false, true).await,
+        }
+
+        if close {
+            return Ok(());
+        }
+    }
+}
+
+/// Maps a request target onto the site root. Anything that could escape it is refused.
+fn resolve_path(root: &Path, target: &str) -> Option<PathBuf> {
+    let path = target.split(['?', '#']).next()?;
+    if !path.starts_with('/') || path.contains('\\') || path.contains('\0') {
+        return None;
+    }
+
+    let mut resolved = root.to_path_buf();
+    for component in Path::new(path.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    if path.ends_with('/') || resolved == root {
+        resolved.push("index.html");
+    }
+    Some(resolved)
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()).unwrap_or_default() {
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" => "application/javascript",
+        "json" => "application/json",
+        "txt" => "text/plain",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "ico" => "image/x-icon",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Sends a file, or a stock nginx-shaped error page when `content` is None,
+/// so probes see nothing unusual.
+async fn respond_page<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    status: &str,
+    content: Option<(&str, &[u8])>,
+    keep_alive: bool,
+    send_body: bool,
+) -> Result<()> {
+    let error_page;
+    let (content_type, body) = match content {
+        Some(content) => content,
+        None => {
+            error_page = format!(
+                "<html>\r\n<head><title>{status}</title></head>\r\n<body>\r\n\
+                 <center><h1>{status}</h1></center>\r\n<hr><center>nginx</center>\r\n\
+                 </body>\r\n</html>\r\n"
+            );
+            ("text/html", error_page.as_bytes())
+        }
+    };
+
+    let date = chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let connection = if keep_alive { "keep-alive" } else { "close" };
+    let headers = [
+        ("Server", "nginx"),
+        ("Date", date.as_str()),
+        ("Content-Type", content_type),
+        ("Connection", connection),
+    ];
+
+    respond(stream, status, &headers, body, send_body).await
+}
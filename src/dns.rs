@@ -0,0 +1,470 @@
+// src/dns.rs
+//
+// DNS over Tor.
+// Queries are answered with Tor RESOLVE / RESOLVE_PTR through the same
+// isolation rules (and cache) as SOCKS streams; nothing touches a local resolver.
+
+use anyhow::{Context, Result};
+use bytes::{Buf, Bytes, BytesMut};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use arti_client::ErrorKind;
+use dns_message_parser::question::{QType, Question};
+use dns_message_parser::rr::{Class, A, AAAA, PTR, RR};
+use dns_message_parser::{Dns, DomainName, Flags, Opcode, RCode};
+use futures::stream::{FuturesUnordered, StreamExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::Semaphore;
+use tokio::time::{sleep, Duration};
+use tokio_rustls::TlsAcceptor;
+use tor_rtcompat::Runtime;
+
+use crate::config::{BlockResponse, ClientAuth, Service, TlsRoute};
+use crate::proxy::ProxyState;
+use crate::resolve;
+use crate::tls::{self, TlsMaterial};
+
+/// TTL of synthetic answers for blocked names
+const ANSWER_TTL_BLOCKED: u32 = 300;
+
+/// Idle time allowed between queries on a DNS stream (RFC 7766 §6.2.3)
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Queries answered concurrently on one stream before reading is paused
+const MAX_PIPELINED: usize = 64;
+
+/// UDP queries resolved at once; further datagrams are dropped
+const MAX_UDP_INFLIGHT: usize = 256;
+
+/// Per-source rate limiter bound; idle sources are pruned, then cleared wholesale
+const MAX_TRACKED_SOURCES: usize = 4096;
+
+/// Largest UDP answer we send, whatever the client advertises (DNS flag day 2020)
+const MAX_UDP_PAYLOAD: usize = 1232;
+
+/// Plain DNS for local stub resolvers: UDP plus TCP on the same address so
+/// truncated answers can be retried. Only ever bound to loopback or a
+/// private interface (enforced when the config is loaded).
+pub async fn start_dns_server<R: Runtime>(state: Arc<ProxyState<R>>, bind_addr: SocketAddr) -> Result<()> {
+    let udp = UdpSocket::bind(bind_addr).await.context("Failed to bind UDP DNS listener")?;
+    let tcp = TcpListener::bind(bind_addr).await.context("Failed to bind TCP DNS listener")?;
+
+    tracing::info!("Local DNS listening on {} (UDP+TCP, {} q/s per source)", bind_addr, state.cfg.dns_rate_limit);
+
+    serve_dns(state, udp, tcp).await
+}
+
+/// Serves already-bound DNS sockets (also used for `torrust run` namespaces).
+pub(crate) async fn serve_dns<R: Runtime>(state: Arc<ProxyState<R>>, udp: UdpSocket, tcp: TcpListener) -> Result<()> {
+    let udp = Arc::new(udp);
+
+    {
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                let (socket, peer_addr) = match tcp.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!("TCP DNS accept failed: {}", e);
+                        continue;
+                    }
+                };
+                let state = state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_dns_stream(socket, state, None).await {
+                        tracing::debug!("DNS session from {} ended: {:#}", peer_addr, e);
+                    }
+                });
+            }
+        });
+    }
+
+    let limiter = RateLimiter::new(state.cfg.dns_rate_limit);
+    let inflight = Arc::new(Semaphore::new(MAX_UDP_INFLIGHT));
+    let mut datagram = vec![0u8; 4096];
+
+    loop {
+        let (len, peer_addr) = udp.recv_from(&mut datagram).await?;
+
+        if !limiter.allow(peer_addr.ip()) {
+            tracing::debug!("DNS rate limit exceeded, dropping query from {}", peer_addr);
+            continue;
+        }
+        let Ok(permit) = inflight.clone().try_acquire_owned() else {
+            tracing::debug!("DNS overloaded, dropping query from {}", peer_addr);
+            continue;
+        };
+
+        let query = datagram[..len].to_vec();
+        let state = state.clone();
+        let udp = udp.clone();
+
+        tokio::spawn(async move {
+            if let Some(response) = answer_query(&state, &query, Transport::Udp, None).await {
+                let _ = udp.send_to(&response, peer_addr).await;
+            }
+            drop(permit);
+        });
+    }
+}
+
+/// Token bucket per source address, so spoofed queries cannot turn the
+/// listener into an amplifier.
+struct RateLimiter {
+    rate: f64,
+    buckets: Mutex<HashMap<IpAddr, (f64, Instant)>>,
+}
+
+impl RateLimiter {
+    fn new(per_second: u32) -> Self {
+        RateLimiter {
+            rate: per_second as f64,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn allow(&self, source: IpAddr) -> bool {
+        let burst = self.rate * 2.0;
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() > MAX_TRACKED_SOURCES {
+            buckets.retain(|_, (_, seen)| now.duration_since(*seen) < Duration::from_secs(10));
+            if buckets.len() > MAX_TRACKED_SOURCES { buckets.clear(); }
+        }
+
+        let (tokens, seen) = buckets.entry(source).or_insert((burst, now));
+        *tokens = (*tokens + now.duration_since(*seen).as_secs_f64() * self.rate).min(burst);
+        *seen = now;
+
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Dedicated DNS-over-TLS listener (RFC 7858). Always requires a client
+/// certificate; there is no decoy on this port.
+pub async fn start_dot_server<R: Runtime>(
+    state: Arc<ProxyState<R>>,
+    material: TlsMaterial,
+    port: u16,
+) -> Result<()> {
+    let route = TlsRoute {
+        service: Service::Dns,
+        sni: None,
+        alpn: Some("dot".to_string()),
+        client_auth: ClientAuth::Required,
+        client_ca: None,
+    };
+    let acceptor = TlsAcceptor::from(Arc::new(tls::server_config(&state.cfg, &material, &route)?));
+
+    let bind_addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let listener = TcpListener::bind(bind_addr).await.context("Failed to bind DoT listener")?;
+
+    tracing::info!("mTLS DNS-over-TLS listening on {}", bind_addr);
+
+    loop {
+        let (socket, peer_addr) = listener.accept().await?;
+
+        if let Err(e) = socket.set_nodelay(true) {
+            tracing::warn!("Failed to set TCP_NODELAY: {}", e);
+        }
+
+        let state = state.clone();
+        let acceptor = acceptor.clone();
+
+        tokio::spawn(async move {
+            match acceptor.accept(socket).await {
+                Ok(tls_stream) => {
+                    let peer_name = tls_stream.get_ref().1.peer_certificates().and_then(tls::peer_common_name);
+                    if let Err(e) = handle_dns_stream(tls_stream, state, peer_name).await {
+                        tracing::debug!("DoT session from {} ended: {:#}", peer_addr, e);
+                    }
+                }
+                Err(e) => tracing::warn!("mTLS handshake failed (Unauthorized DoT probe dropped from {}): {}", peer_addr, e),
+            }
+        });
+    }
+}
+
+/// Serves RFC 1035 §4.2.2 length-prefixed DNS messages on a stream.
+/// Queries are pipelined (RFC 7766 §6.2.1.1): each is resolved as soon as it
+/// arrives and answers go out in completion order, matched by message ID.
+pub async fn handle_dns_stream<R: Runtime, S>(
+    mut client: S,
+    state: Arc<ProxyState<R>>,
+    peer_name: Option<String>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut inbound = BytesMut::with_capacity(4096);
+    let mut pending = FuturesUnordered::new();
+    let mut eof = false;
+
+    loop {
+        // Start every complete query already buffered
+        while pending.len() < MAX_PIPELINED {
+            let Some(query) = next_frame(&mut inbound) else { break };
+            let state = state.clone();
+            let peer_name = peer_name.as_deref();
+            pending.push(async move { answer_query(&state, &query, Transport::Stream, peer_name).await });
+        }
+
+        if eof && pending.is_empty() {
+            return Ok(());
+        }
+
+        tokio::select! {
+            read = client.read_buf(&mut inbound), if !eof && pending.len() < MAX_PIPELINED => {
+                if read.context("DNS stream read failed")? == 0 {
+                    if !inbound.is_empty() {
+                        tracing::debug!("Truncated DNS message discarded");
+                    }
+                    eof = true;
+                }
+            }
+            Some(response) = pending.next(), if !pending.is_empty() => {
+                if let Some(response) = response {
+                    client.write_all(&(response.len() as u16).to_be_bytes()).await?;
+                    client.write_all(&response).await?;
+                    client.flush().await?;
+                }
+            }
+            // Idle only counts while nothing is outstanding (RFC 7766 §6.2.3)
+            _ = sleep(IDLE_TIMEOUT), if pending.is_empty() => return Ok(()),
+        }
+    }
+}
+
+/// Pops one length-prefixed message off the front of the buffer.
+fn next_frame(buf: &mut BytesMut) -> Option<Vec<u8>> {
+    if buf.len() < 2 {
+        return None;
+    }
+    let len = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+    if buf.len() < 2 + len {
+        return None;
+    }
+    buf.advance(2);
+    Some(buf.split_to(len).to_vec())
+}
+
+/// How the response will travel; datagrams are size-limited
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Stream,
+    Udp,
+}
+
+/// Builds the wire-format response to one query. `None` means drop silently
+/// (the message was too broken to even echo an ID).
+pub async fn answer_query<R: Runtime>(
+    state: &ProxyState<R>,
+    query: &[u8],
+    transport: Transport,
+    peer_name: Option<&str>,
+) -> Option<Vec<u8>> {
+    let request = match Dns::decode(Bytes::copy_from_slice(query)) {
+        Ok(request) => request,
+        Err(_) => return error_header(query, RCode::FormErr),
+    };
+
+    if request.flags.qr || request.flags.opcode != Opcode::Query || request.questions.len() != 1 {
+        let rcode = if request.flags.opcode != Opcode::Query { RCode::NotImp } else { RCode::FormErr };
+        return encode(response(&request, rcode, Vec::new()));
+    }
+
+    let question = &request.questions[0];
+    let name = question.domain_name.to_string().trim_end_matches('.').to_ascii_lowercase();
+
+    let (rcode, answers) = match question.q_type {
+        _ if state.filter.is_blocked(peer_name, &name) => {
+            tracing::debug!("Blocked by domain policy: {}", name);
+            blocked_answer(state.cfg.domain_block_response, question)
+        }
+        QType::A | QType::AAAA => resolve_forward(state, question, &name).await,
+        QType::PTR => resolve_reverse(state, question, &name).await,
+        // Other types (HTTPS, SVCB, MX...) get NODATA rather than an error
+        _ => (RCode::NoError, Vec::new()),
+    };
+
+    let encoded = encode(response(&request, rcode, answers))?;
+    if transport == Transport::Udp && encoded.len() > udp_payload_limit(&request) {
+        // Header and question only; TC=1 sends the client to TCP
+        let mut truncated = response(&request, rcode, Vec::new());
+        truncated.flags.tc = true;
+        return encode(truncated);
+    }
+    Some(encoded)
+}
+
+/// 512 bytes unless the query carries an EDNS(0) OPT record (RFC 6891 §6.2.5).
+fn udp_payload_limit(request: &Dns) -> usize {
+    request
+        .additionals
+        .iter()
+        .find_map(|rr| match rr {
+            RR::OPT(opt) => Some((opt.requestor_payload_size as usize).clamp(512, MAX_UDP_PAYLOAD)),
+            _ => None,
+        })
+        .unwrap_or(512)
+}
+
+async fn resolve_forward<R: Runtime>(state: &ProxyState<R>, question: &Question, name: &str) -> (RCode, Vec<RR>) {
+    let token = state.isolation_token(None, name);
+
+    let (addrs, ttl) = match resolve::lookup_host(state, name, token).await {
+        Ok(found) => found,
+        Err(kind) => return (failure_rcode(kind), Vec::new()),
+    };
+
+    let answers = addrs
+        .into_iter()
+        .filter_map(|addr| match (addr, question.q_type) {
+            (IpAddr::V4(ipv4_addr), QType::A) => Some(RR::A(A {
+                domain_name: question.domain_name.clone(),
+                ttl,
+                ipv4_addr,
+            })),
+            (IpAddr::V6(ipv6_addr), QType::AAAA) => Some(RR::AAAA(AAAA {
+                domain_name: question.domain_name.clone(),
+                ttl,
+                ipv6_addr,
+            })),
+            _ => None,
+        })
+        .collect();
+
+    (RCode::NoError, answers)
+}
+
+async fn resolve_reverse<R: Runtime>(state: &ProxyState<R>, question: &Question, name: &str) -> (RCode, Vec<RR>) {
+    let Some(addr) = parse_reverse_name(name) else {
+        return (RCode::NXDomain, Vec::new());
+    };
+
+    let token = state.isolation_token(None, name);
+
+    let (names, ttl) = match resolve::lookup_ptr(state, addr, token).await {
+        Ok(found) => found,
+        Err(kind) => return (failure_rcode(kind), Vec::new()),
+    };
+
+    let answers = names
+        .into_iter()
+        .filter_map(|ptr| ptr.parse::<DomainName>().ok())
+        .map(|ptr_d_name| {
+            RR::PTR(PTR {
+                domain_name: question.domain_name.clone(),
+                ttl,
+                class: Class::IN,
+                ptr_d_name,
+            })
+        })
+        .collect();
+
+    (RCode::NoError, answers)
+}
+
+/// NXDOMAIN, or an unroutable address so clients fail fast without retrying.
+fn blocked_answer(mode: BlockResponse, question: &Question) -> (RCode, Vec<RR>) {
+    if mode == BlockResponse::NxDomain {
+        return (RCode::NXDomain, Vec::new());
+    }
+
+    let answer = match question.q_type {
+        QType::A => RR::A(A {
+            domain_name: question.domain_name.clone(),
+            ttl: ANSWER_TTL_BLOCKED,
+            ipv4_addr: Ipv4Addr::UNSPECIFIED,
+        }),
+        QType::AAAA => RR::AAAA(AAAA {
+            domain_name: question.domain_name.clone(),
+            ttl: ANSWER_TTL_BLOCKED,
+            ipv6_addr: Ipv6Addr::UNSPECIFIED,
+        }),
+        _ => return (RCode::NoError, Vec::new()),
+    };
+    (RCode::NoError, vec![answer])
+}
+
+fn failure_rcode(kind: ErrorKind) -> RCode {
+    match kind {
+        ErrorKind::RemoteHostNotFound => RCode::NXDomain,
+        _ => RCode::ServFail,
+    }
+}
+
+/// Parses `d.c.b.a.in-addr.arpa` and nibble-format `ip6.arpa` names.
+fn parse_reverse_name(name: &str) -> Option<IpAddr> {
+    if let Some(rest) = name.strip_suffix(".in-addr.arpa") {
+        let mut octets: Vec<u8> = rest.split('.').map(|o| o.parse().ok()).collect::<Option<_>>()?;
+        if octets.len() != 4 {
+            return None;
+        }
+        octets.reverse();
+        return Some(IpAddr::V4(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3])));
+    }
+
+    let rest = name.strip_suffix(".ip6.arpa")?;
+    let nibbles: Vec<u8> = rest
+        .split('.')
+        .rev()
+        .map(|n| if n.len() == 1 { u8::from_str_radix(n, 16).ok() } else { None })
+        .collect::<Option<_>>()?;
+    if nibbles.len() != 32 {
+        return None;
+    }
+
+    let mut octets = [0u8; 16];
+    for (i, pair) in nibbles.chunks(2).enumerate() {
+        octets[i] = (pair[0] << 4) | pair[1];
+    }
+    Some(IpAddr::V6(Ipv6Addr::from(octets)))
+}
+
+fn response(request: &Dns, rcode: RCode, answers: Vec<RR>) -> Dns {
+    Dns {
+        id: request.id,
+        flags: Flags {
+            qr: true,
+            opcode: request.flags.opcode,
+            aa: false,
+            tc: false,
+            rd: request.flags.rd,
+            ra: true,
+            ad: false,
+            cd: false,
+            rcode,
+        },
+        questions: request.questions.clone(),
+        answers,
+        authorities: Vec::new(),
+        additionals: Vec::new(),
+    }
+}
+
+fn encode(dns: Dns) -> Option<Vec<u8>> {
+    dns.encode().ok().map(|b| b.to_vec())
+}
+
+/// Bare header-only error reply for messages that failed to parse.
+fn error_header(query: &[u8], rcode: RCode) -> Option<Vec<u8>> {
+    if query.len() < 12 {
+        return None;
+    }
+    let mut header = vec![0u8; 12];
+    header[..2].copy_from_slice(&query[..2]);
+    header[2] = 0x80 | (query[2] & 0x79); // QR=1, keep opcode and RD
+    header[3] = 0x80 | (rcode as u8); // RA=1
+    Some(header)
+}
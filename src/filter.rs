@@ -0,0 +1,328 @@
+// src/filter.rs
+//
+// Domain policy: blocklists, allowlists and per-client overrides.
+// Blocked names never reach Tor, so they never build a circuit.
+//
+// Accepted list lines (format detected per line):
+//   0.0.0.0 ads.example.com       hosts file, exact name
+//   ||tracker.example^            AdBlock, name and subdomains
+//   @@||cdn.tracker.example^      AdBlock exception (allow)
+//   telemetry.example.com         plain domain, name and subdomains
+//   *.metrics.*                   wildcard, '*' matches any characters
+
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::config::{ClientRules, Config};
+
+/// Name sets loaded from one or more lists
+#[derive(Default)]
+struct Rules {
+    exact: HashSet<String>,
+    suffixes: HashSet<String>,
+    wildcards: Vec<String>,
+}
+
+impl Rules {
+    fn matches(&self, name: &str) -> bool {
+        if self.exact.contains(name) {
+            return true;
+        }
+
+        let mut rest = name;
+        loop {
+            if self.suffixes.contains(rest) {
+                return true;
+            }
+            match rest.split_once('.') {
+                Some((_, parent)) => rest = parent,
+                None => break,
+            }
+        }
+
+        self.wildcards.iter().any(|pattern| wildcard_match(pattern, name))
+    }
+
+    fn len(&self) -> usize {
+        self.exact.len() + self.suffixes.len() + self.wildcards.len()
+    }
+}
+
+/// Verdict for one name
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    Block,
+}
+
+struct Policy {
+    allow: Rules,
+    block: Rules,
+}
+
+impl Policy {
+    fn load(allow: &[impl AsRef<Path>], block: &[impl AsRef<Path>]) -> Result<Self> {
+        let mut policy = Policy { allow: Rules::default(), block: Rules::default() };
+        for path in block {
+            load_list(path.as_ref(), &mut policy.block, &mut policy.allow)?;
+        }
+        for path in allow {
+            // Everything in an allowlist allows, whatever its syntax
+            let mut allow = Rules::default();
+            load_list(path.as_ref(), &mut allow, &mut Rules::default())?;
+            merge(&mut policy.allow, allow);
+        }
+        Ok(policy)
+    }
+
+    fn verdict(&self, name: &str) -> Option<Verdict> {
+        if self.allow.matches(name) {
+            Some(Verdict::Allow)
+        } else if self.block.matches(name) {
+            Some(Verdict::Block)
+        } else {
+            None
+        }
+    }
+}
+
+pub struct DomainFilter {
+    global: Policy,
+    /// Keyed by client certificate common name
+    clients: HashMap<String, Policy>,
+}
+
+impl DomainFilter {
+    pub fn load(cfg: &Config) -> Result<Self> {
+        let global = Policy::load(&cfg.domain_allowlists, &cfg.domain_blocklists)?;
+
+        let mut clients = HashMap::new();
+        for ClientRules { client, allow, block } in &cfg.domain_client_rules {
+            let policy = Policy::load(allow, block)?;
+            tracing::info!(
+                "Domain policy for client '{}': {} block / {} allow rules",
+                client,
+                policy.block.len(),
+                policy.allow.len()
+            );
+            clients.insert(client.clone(), policy);
+        }
+
+        if global.block.len() + global.allow.len() > 0 {
+            tracing::info!(
+                "Domain policy: {} block / {} allow rules",
+                global.block.len(),
+                global.allow.len()
+            );
+        }
+
+        Ok(DomainFilter { global, clients })
+    }
+
+    /// Client rules win over global ones; within each, allow wins over block.
+    pub fn verdict(&self, client: Option<&str>, name: &str) -> Verdict {
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+
+        client
+            .and_then(|c| self.clients.get(c))
+            .and_then(|policy| policy.verdict(&name))
+            .or_else(|| self.global.verdict(&name))
+            .unwrap_or(Verdict::Allow)
+    }
+
+    pub fn is_blocked(&self, client: Option<&str>, name: &str) -> bool {
+        self.verdict(client, name) == Verdict::Block
+    }
+}
+
+fn load_list(path: &Path, block: &mut Rules, allow: &mut Rules) -> Result<()> {
+    let text = fs::read_to_string(path).with_context(|| format!("Cannot read domain list {:?}", path))?;
+
+    let mut skipped = 0usize;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', '!', '[']) {
+            continue;
+        }
+        if !parse_line(line, block, allow) {
+            skipped += 1;
+        }
+    }
+
+    if skipped > 0 {
+        tracing::debug!("Domain list {:?}: {} unsupported lines ignored", path, skipped);
+    }
+    Ok(())
+}
+
+/// Returns false for lines that cannot be enforced at the name level
+/// (cosmetic filters, URL paths, rules with modifiers).
+fn parse_line(line: &str, block: &mut Rules, allow: &mut Rules) -> bool {
+    // AdBlock network rules
+    if let Some(rule) = line.strip_prefix("@@||") {
+        return adblock_domain(rule).map(|d| allow.suffixes.insert(d)).is_some();
+    }
+    if let Some(rule) = line.strip_prefix("||") {
+        return adblock_domain(rule).map(|d| block.suffixes.insert(d)).is_some();
+    }
+
+    let line = line.split('#').next().unwrap_or_default().trim();
+    let mut fields = line.split_whitespace();
+    let Some(first) = fields.next() else { return true };
+
+    // hosts format: address followed by one or more names
+    if first.parse::<std::net::IpAddr>().is_ok() {
+        for name in fields {
+            let name = name.to_ascii_lowercase();
+            if !matches!(name.as_str(), "localhost" | "localhost.localdomain" | "broadcasthost" | "local" | "0.0.0.0")
+                && is_domain(&name)
+            {
+                block.exact.insert(name);
+            }
+        }
+        return true;
+    }
+
+    if fields.next().is_some() {
+        return false;
+    }
+
+    let name = first.trim_end_matches('.').to_ascii_lowercase();
+    if name.contains('*') {
+        block.wildcards.push(name);
+        true
+    } else if is_domain(&name) {
+        block.suffixes.insert(name);
+        true
+    } else {
+        false
+    }
+}
+
+/// `example.com^` (optionally `|`-terminated); anything with modifiers or a path is skipped.
+fn adblock_domain(rule: &str) -> Option<String> {
+    let domain = rule.strip_suffix('^').or_else(|| rule.strip_suffix("^|"))?;
+    let domain = domain.to_ascii_lowercase();
+    is_domain(&domain).then_some(domain)
+}
+
+fn is_domain(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        })
+}
+
+/// Glob match where `*` spans any run of characters, dots included.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let (p, n) = (pattern.as_bytes(), name.as_bytes());
+    let (mut pi, mut ni) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while ni < n.len() {
+        if pi < p.len() && p[pi] == b'*' {
+            backtrack = Some((pi, ni));
+            pi += 1;
+        } else if pi < p.len() && p[pi] == n[ni] {
+            pi += 1;
+            ni += 1;
+        } else if let Some((star, matched)) = backtrack {
+            pi = star + 1;
+            ni = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    p[pi..].iter().all(|&b| b == b'*')
+}
+
+fn merge(into: &mut Rules, from: Rules) {
+    into.exact.extend(from.exact);
+    into.suffixes.extend(from.suffixes);
+    into.wildcards.extend(from.wildcards);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Writes a list to a file unique to this test process
+    fn list(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("torrust-filter-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn global(allow: &[PathBuf], block: &[PathBuf]) -> DomainFilter {
+        DomainFilter { global: Policy::load(allow, block).unwrap(), clients: HashMap::new() }
+    }
+
+    #[test]
+    fn suffix_and_exact_matches() {
+        let block = list(
+            "suffix-exact",
+            "# comment\n\
+             0.0.0.0 ads.example.com localhost\n\
+             telemetry.example.com\n\
+             ||tracker.example^\n\
+             @@||cdn.tracker.example^\n\
+             *.metrics.*\n\
+             ||example.net/path^\n",
+        );
+        let filter = global(&[], &[block]);
+
+        // hosts lines block the exact name only
+        assert!(filter.is_blocked(None, "ads.example.com"));
+        assert!(!filter.is_blocked(None, "sub.ads.example.com"));
+        assert!(!filter.is_blocked(None, "localhost"));
+
+        // plain and AdBlock domains block the name and its subdomains
+        assert!(filter.is_blocked(None, "telemetry.example.com"));
+        assert!(filter.is_blocked(None, "eu.telemetry.example.com"));
+        assert!(!filter.is_blocked(None, "example.com"));
+        assert!(!filter.is_blocked(None, "xtelemetry.example.com"));
+        assert!(filter.is_blocked(None, "a.tracker.example"));
+        assert!(!filter.is_blocked(None, "cdn.tracker.example"));
+
+        assert!(filter.is_blocked(None, "eu.metrics.example.org"));
+        assert!(!filter.is_blocked(None, "metrics.example.org"));
+
+        // Rules with a path cannot be enforced on names and are skipped
+        assert!(!filter.is_blocked(None, "example.net"));
+    }
+
+    #[test]
+    fn case_and_trailing_dot() {
+        let block = list("case", "Telemetry.Example.COM.\n0.0.0.0 ADS.example.com\n");
+        let filter = global(&[], &[block]);
+
+        assert!(filter.is_blocked(None, "TELEMETRY.example.com"));
+        assert!(filter.is_blocked(None, "telemetry.example.com."));
+        assert!(filter.is_blocked(None, "Ads.Example.Com."));
+    }
+
+    #[test]
+    fn allow_overrides_block() {
+        let block = list("override-block", "example.org\n");
+        let allow = list("override-allow", "www.example.org\n");
+        let client_allow = list("override-client", "example.org\n");
+
+        let mut filter = global(&[allow], &[block]);
+        filter.clients.insert("alice".to_string(), Policy::load(&[client_allow], &[] as &[PathBuf]).unwrap());
+
+        assert!(!filter.is_blocked(None, "www.example.org"));
+        assert!(filter.is_blocked(None, "api.example.org"));
+
+        // A client's allow wins over the global deny, for that client only
+        assert!(!filter.is_blocked(Some("alice"), "api.example.org"));
+        assert!(filter.is_blocked(Some("bob"), "api.example.org"));
+    }
+}
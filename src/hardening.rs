@@ -21,7 +21,8 @@ use rlimit::Resource;
 
 /// Applies a suite of security hardening measures to the current process.
 /// If 'strict' is true, failures in critical protections like mlockall will abort startup.
-pub fn apply_protections(strict: bool) -> Result<()> {
+/// 'spawns_children' keeps process creation available (e.g. `torrust run`).
+pub fn apply_protections(strict: bool, spawns_children: bool) -> Result<()> {
     #[cfg(unix)]
     {
         // 1. Lock virtual memory into physical RAM (prevents disk paging/swap)
@@ -71,8 +72,10 @@ pub fn apply_protections(strict: bool) -> Result<()> {
 
         // 5. Resource exhaustion protection
         // Restrict the ability to spawn new processes to prevent fork bombs.
-        rlimit::setrlimit(Resource::NPROC, 0, 0)
-            .context("Failed to restrict process spawning (NPROC)")?;
+        if !spawns_children {
+            rlimit::setrlimit(Resource::NPROC, 0, 0)
+                .context("Failed to restrict process spawning (NPROC)")?;
+        }
             
         // Lift the file write size limit to allow normal operation, but prevent core files.
         rlimit::setrlimit(Resource::FSIZE, u64::MAX, u64::MAX)
@@ -0,0 +1,189 @@
+// src/hostname.rs
+//
+// Strict validation of requested destinations before they reach Tor.
+// Names are reduced to lowercase LDH A-labels; v3 onion addresses must carry
+// a valid checksum, so typos fail here instead of after a descriptor lookup.
+
+use std::net::IpAddr;
+
+use data_encoding::BASE32_NOPAD;
+use sha3::{Digest, Sha3_256};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostError {
+    /// Empty, too long, control characters, not LDH/IDNA, or an IP address
+    /// disguised as a name (`127.1`, `2130706433`, `0x7f000001`)
+    Malformed,
+    /// `.onion` name that is not a well-formed v3 address
+    BadOnion,
+}
+
+/// Canonical form of a requested host: IP literals lose any brackets,
+/// names are lowercased and IDNA-encoded, then checked label by label.
+pub fn canonicalize(host: &str) -> Result<String, HostError> {
+    if let Ok(addr) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        return Ok(addr.to_string());
+    }
+
+    let name = host.strip_suffix('.').unwrap_or(host);
+    if name.is_empty() || name.chars().any(|c| c.is_control()) {
+        return Err(HostError::Malformed);
+    }
+
+    let name = if name.is_ascii() {
+        name.to_ascii_lowercase()
+    } else {
+        idna::domain_to_ascii_strict(name).map_err(|_| HostError::Malformed)?
+    };
+
+    if name.len() > 253 || !name.split('.').all(is_ldh_label) || is_disguised_ip(&name) {
+        return Err(HostError::Malformed);
+    }
+
+    if name.ends_with(".onion") || name == "onion" {
+        check_onion(&name)?;
+    }
+
+    Ok(name)
+}
+
+/// Whether the host is an IPv4 or (optionally bracketed) IPv6 literal.
+pub fn is_ip_literal(host: &str) -> bool {
+    host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().is_ok()
+}
+
+/// Whether a name would be read as an IPv4 address by inet_aton-style
+/// parsers (shorthand, decimal, octal or hex forms). No TLD is all-numeric,
+/// so a numeric or `0x` last label is enough to tell.
+pub fn is_disguised_ip(host: &str) -> bool {
+    let name = host.strip_suffix('.').unwrap_or(host);
+    let last = name.rsplit('.').next().unwrap_or_default();
+    if let Some(hex) = last.strip_prefix("0x").or_else(|| last.strip_prefix("0X")) {
+        return hex.bytes().all(|b| b.is_ascii_hexdigit());
+    }
+    !last.is_empty() && last.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Letters, digits and inner hyphens (underscores tolerated, as resolvers do).
+fn is_ldh_label(label: &str) -> bool {
+    !label.is_empty()
+        && label.len() <= 63
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+        && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// rend-spec-v3 §6: base32(PUBKEY | CHECKSUM | VERSION), with
+/// CHECKSUM = SHA3-256(".onion checksum" | PUBKEY | VERSION)[..2] and VERSION = 3.
+fn check_onion(name: &str) -> Result<(), HostError> {
+    let labels: Vec<&str> = name.split('.').collect();
+    if labels.len() < 2 {
+        return Err(HostError::BadOnion);
+    }
+
+    // Subdomains (www.<addr>.onion) are allowed; the address is the label before .onion
+    let address = labels[labels.len() - 2];
+    if address.len() != 56 {
+        return Err(HostError::BadOnion);
+    }
+
+    let decoded = BASE32_NOPAD
+        .decode(address.to_ascii_uppercase().as_bytes())
+        .map_err(|_| HostError::BadOnion)?;
+    let (pubkey, rest) = decoded.split_at(32);
+    let (checksum, version) = rest.split_at(2);
+
+    if version != [3] {
+        return Err(HostError::BadOnion);
+    }
+
+    let mut hasher = Sha3_256::new();
+    hasher.update(b".onion checksum");
+    hasher.update(pubkey);
+    hasher.update(version);
+    let digest = hasher.finalize();
+
+    if digest[..2] != *checksum {
+        return Err(HostError::BadOnion);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONION: &str = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion";
+
+    /// v3-style address for `pubkey` with the given version byte and a correct checksum
+    fn onion_with_version(pubkey: &[u8; 32], version: u8) -> String {
+        let mut hasher = Sha3_256::new();
+        hasher.update(b".onion checksum");
+        hasher.update(pubkey);
+        hasher.update([version]);
+        let digest = hasher.finalize();
+
+        let mut raw = pubkey.to_vec();
+        raw.extend_from_slice(&digest[..2]);
+        raw.push(version);
+        format!("{}.onion", BASE32_NOPAD.encode(&raw).to_ascii_lowercase())
+    }
+
+    #[test]
+    fn disguised_ipv4_is_rejected() {
+        for host in ["127.1", "2130706433", "0x7f000001", "0X7F000001", "0177.0.0.1", "127.0.0.1.", "10.0x1", "a.0x"] {
+            assert!(is_disguised_ip(host), "{}", host);
+            assert_eq!(canonicalize(host), Err(HostError::Malformed), "{}", host);
+        }
+        for host in ["3com.com", "1.0.0.127.in-addr.arpa", "0x.example", "a.0xg"] {
+            assert!(!is_disguised_ip(host), "{}", host);
+            assert!(canonicalize(host).is_ok(), "{}", host);
+        }
+    }
+
+    #[test]
+    fn ip_literals_are_normalised() {
+        assert_eq!(canonicalize("127.0.0.1").as_deref(), Ok("127.0.0.1"));
+        assert_eq!(canonicalize("[::1]").as_deref(), Ok("::1"));
+        assert!(is_ip_literal("[2001:db8::1]"));
+        assert!(!is_ip_literal("127.1"));
+    }
+
+    #[test]
+    fn onion_checksum_and_version() {
+        assert_eq!(canonicalize(ONION).as_deref(), Ok(ONION));
+        assert_eq!(canonicalize(&format!("www.{}", ONION.to_ascii_uppercase())), Ok(format!("www.{}", ONION)));
+
+        // One flipped character breaks the checksum
+        let typo = ONION.replacen("duck", "dvck", 1);
+        assert_eq!(canonicalize(&typo), Err(HostError::BadOnion));
+
+        let pubkey = [7u8; 32];
+        assert!(canonicalize(&onion_with_version(&pubkey, 3)).is_ok());
+        assert_eq!(canonicalize(&onion_with_version(&pubkey, 4)), Err(HostError::BadOnion));
+
+        // v2 length, and a bare TLD
+        assert_eq!(canonicalize("expyuzz4wqqyqhjn.onion"), Err(HostError::BadOnion));
+        assert_eq!(canonicalize("onion"), Err(HostError::BadOnion));
+    }
+
+    #[test]
+    fn label_and_name_lengths() {
+        let label = "a".repeat(63);
+        assert!(canonicalize(&format!("{}.com", label)).is_ok());
+        assert_eq!(canonicalize(&format!("{}a.com", label)), Err(HostError::Malformed));
+
+        let long = vec!["abcdefghi"; 26].join(".");
+        assert_eq!(long.len(), 259);
+        assert_eq!(canonicalize(&long), Err(HostError::Malformed));
+    }
+
+    #[test]
+    fn trailing_dot_case_and_idna() {
+        assert_eq!(canonicalize("Example.COM.").as_deref(), Ok("example.com"));
+        assert_eq!(canonicalize("bücher.example").as_deref(), Ok("xn--bcher-kva.example"));
+        for host in ["", ".", "example..com", "-example.com", "exa mple.com", "exa\u{7}mple.com"] {
+            assert_eq!(canonicalize(host), Err(HostError::Malformed), "{:?}", host);
+        }
+    }
+}
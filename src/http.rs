@@ -0,0 +1,103 @@
+// src/http.rs
+//
+// Minimal HTTP/1.1 framing shared by the decoy site, HTTP CONNECT and the admin API.
+// Only request heads are parsed; bodies are never read.
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Upper bound for a request head (request line + headers)
+pub const MAX_HEAD: usize = 8192;
+
+/// A parsed request head
+pub struct RequestHead {
+    pub method: String,
+    pub target: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl RequestHead {
+    /// Case-insensitive header lookup
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn wants_close(&self) -> bool {
+        self.header("connection").is_some_and(|v| v.eq_ignore_ascii_case("close"))
+    }
+}
+
+/// Reads one request head. Leftover bytes (pipelined data) stay in `buf`.
+/// Returns `None` on a clean EOF before any byte of a new request.
+pub async fn read_head<S: AsyncRead + Unpin>(stream: &mut S, buf: &mut Vec<u8>) -> Result<Option<RequestHead>> {
+    loop {
+        if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            let head = std::str::from_utf8(&buf[..end]).context("Non UTF-8 request head")?;
+            let parsed = parse_head(head)?;
+            buf.drain(..end + 4);
+            return Ok(Some(parsed));
+        }
+        if buf.len() > MAX_HEAD {
+            anyhow::bail!("Request head too large");
+        }
+
+        let mut chunk = [0u8; 1024];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            anyhow::bail!("Connection closed mid-request");
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+fn parse_head(head: &str) -> Result<RequestHead> {
+    let mut lines = head.split("\r\n");
+    let mut parts = lines.next().unwrap_or_default().split(' ');
+
+    let (method, target) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(m), Some(t), Some(v), None) if !m.is_empty() && !t.is_empty() && v.starts_with("HTTP/1.") => (m, t),
+        _ => anyhow::bail!("Malformed request line"),
+    };
+
+    let headers = lines
+        .map(|line| {
+            line.split_once(':')
+                .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+                .context("Malformed header line")
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(RequestHead {
+        method: method.to_string(),
+        target: target.to_string(),
+        headers,
+    })
+}
+
+/// Writes a complete response. `send_body` is false for HEAD requests.
+pub async fn respond<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    status: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    send_body: bool,
+) -> Result<()> {
+    let mut head = format!("HTTP/1.1 {status}\r\n");
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+
+    stream.write_all(head.as_bytes()).await?;
+    if send_body {
+        stream.write_all(body).await?;
+    }
+    stream.flush().await?;
+    Ok(())
+}
@@ -0,0 +1,161 @@
+// src/http_connect.rs
+//
+// HTTP CONNECT front-end for clients without SOCKS support.
+// Same Tor connect path and isolation rules as SOCKS; Proxy-Authorization
+// plays the role of SOCKS username/password for stream isolation.
+
+use anyhow::Result;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::time::{timeout, Duration};
+use tor_rtcompat::Runtime;
+use zeroize::Zeroize;
+
+use crate::http::{read_head, respond};
+use crate::proxy::{connect_tor, relay, ProxyState};
+
+pub async fn handle_http_connect<R: Runtime, S>(
+    mut client: S,
+    state: Arc<ProxyState<R>>,
+    peer_name: Option<String>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut buf = Vec::with_capacity(1024);
+
+    let head = match timeout(Duration::from_secs(10), read_head(&mut client, &mut buf)).await {
+        Ok(Ok(Some(head))) => head,
+        Ok(Ok(None)) => return Ok(()),
+        Ok(Err(e)) => {
+            let _ = respond(&mut client, "400 Bad Request", &[], b"", false).await;
+            return Err(e);
+        }
+        Err(_) => {
+            tracing::warn!("HTTP CONNECT Handshake Timeout");
+            return Ok(());
+        }
+    };
+
+    if head.method != "CONNECT" {
+        respond(&mut client, "405 Method Not Allowed", &[("Allow", "CONNECT")], b"", false).await?;
+        return Ok(());
+    }
+
+    let Some((mut host, port)) = split_authority(&head.target) else {
+        respond(&mut client, "400 Bad Request", &[], b"", false).await?;
+        return Ok(());
+    };
+
+    match crate::hostname::canonicalize(&host) {
+        Ok(canonical) => {
+            host.zeroize();
+            host = canonical;
+        }
+        Err(_) if crate::hostname::is_disguised_ip(&host) => {
+            tracing::warn!(
+                "Rejected HTTP CONNECT target that is an IP address disguised as a name: the \
+                 application is resolving DNS locally, which leaks lookups"
+            );
+            host.zeroize();
+            respond(&mut client, "400 Bad Request", &[], b"", false).await?;
+            return Ok(());
+        }
+        Err(e) => {
+            tracing::warn!("Rejected HTTP CONNECT target: {:?}", e);
+            host.zeroize();
+            respond(&mut client, "400 Bad Request", &[], b"", false).await?;
+            return Ok(());
+        }
+    }
+
+    if !state.automap.rewrite(&mut host) {
+        tracing::warn!("HTTP CONNECT target is an unmapped virtual address");
+        host.zeroize();
+        respond(&mut client, "502 Bad Gateway", &[], b"", false).await?;
+        return Ok(());
+    }
+
+    if state.filter.is_blocked(peer_name.as_deref(), &host) {
+        tracing::debug!("Blocked by domain policy: {}", host);
+        host.zeroize();
+        respond(&mut client, "403 Forbidden", &[], b"", false).await?;
+        return Ok(());
+    }
+
+    let cred_hash = head.header("proxy-authorization").map(|v| {
+        let mut hasher = DefaultHasher::new();
+        v.hash(&mut hasher);
+        hasher.finish()
+    });
+    drop(head);
+
+    // Isolation follows the name the client asked for, not the MapAddress target
+    let token = state.isolation_token(cred_hash, &host);
+
+    if let Some(target) = state.rewrites.rewrite(&host) {
+        tracing::debug!("MapAddress: {} -> {}", host, target);
+        host.zeroize();
+        host = target;
+
+        // A rewrite must not lead around the blocklists
+        if state.filter.is_blocked(peer_name.as_deref(), &host) {
+            tracing::debug!("Blocked by domain policy (MapAddress target): {}", host);
+            host.zeroize();
+            respond(&mut client, "403 Forbidden", &[], b"", false).await?;
+            return Ok(());
+        }
+    }
+
+    if !crate::policy::permits(&state.cfg, &host, port) {
+        host.zeroize();
+        respond(&mut client, "403 Forbidden", &[], b"", false).await?;
+        return Ok(());
+    }
+
+    tracing::debug!("Routing {}:{} through Tor (HTTP CONNECT)...", host, port);
+
+    let tor_stream_result = connect_tor(&state, &host, port, token).await;
+    host.zeroize();
+
+    let mut tor_stream = match tor_stream_result {
+        Ok(stream) => stream,
+        Err(e) => {
+            tracing::warn!("Tor failed to route to target: {}", e);
+            respond(&mut client, "502 Bad Gateway", &[], b"", false).await?;
+            return Ok(());
+        }
+    };
+
+    client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").await?;
+    client.flush().await?;
+
+    // Bytes the client sent right after the request head (e.g. a TLS ClientHello)
+    if !buf.is_empty() {
+        tor_stream.write_all(&buf).await?;
+        tor_stream.flush().await?;
+        buf.zeroize();
+    }
+
+    relay(client, tor_stream).await;
+    Ok(())
+}
+
+/// Splits `host:port` or `[v6]:port`; the port is mandatory for CONNECT.
+fn split_authority(target: &str) -> Option<(String, u16)> {
+    let (host, port) = match target.strip_prefix('[') {
+        Some(rest) => {
+            let (host, port) = rest.split_once("]:")?;
+            (host, port)
+        }
+        None => target.rsplit_once(':')?,
+    };
+
+    if host.is_empty() {
+        return None;
+    }
+    Some((host.to_string(), port.parse().ok()?))
+}
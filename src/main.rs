@@ -6,7 +6,33 @@
 mod config;
 mod proxy;
 mod chaff;
+mod decoy;
 mod hardening;
+mod identity;
+mod tls;
+mod router;
+mod http;
+mod http_connect;
+mod dns;
+mod resolve;
+mod automap;
+mod filter;
+mod policy;
+mod hostname;
+mod mapaddress;
+mod transparent;
+mod sandbox;
+mod onion;
+mod clientauth;
+mod pow;
+mod bridges;
+mod upstream;
+mod tunnel;
+mod chain;
+mod tuning;
+mod overlay;
+mod testnet;
+mod admin;
 
 // ------------------------------------------------------------
 // PARANOIA TIER: Enforce the secure memory allocator globally
@@ -16,8 +42,8 @@ mod hardening;
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
 use anyhow::{Context, Result};
-use clap::Parser;
-use std::{fs, sync::Arc};
+use clap::{Parser, Subcommand};
+use std::{ffi::OsString, fs, sync::Arc};
 use tracing::{error, info, warn};
 use tracing_subscriber::{fmt, EnvFilter, prelude::*};
 
@@ -28,6 +54,8 @@ use arti_client::{
 };
 
 use rustls::crypto::ring;
+use tor_config::ExplicitOrAuto;
+use tor_keymgr::config::ArtiKeystoreKind;
 use tokio::signal;
 
 #[derive(Parser, Debug)]
@@ -36,6 +64,24 @@ struct Args {
     /// Exit immediately after successful startup
     #[arg(long)]
     selfcheck: bool,
+
+    /// Allow a private Tor network (TOR_DIR_AUTHORITIES etc.); refused in strict mode
+    #[arg(long)]
+    testing_network: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a program in a private network namespace whose only network route is Tor.
+    /// /run, /var/run and /tmp are masked; Unix sockets elsewhere stay reachable.
+    Run {
+        /// Program and arguments, after `--`
+        #[arg(required = true, last = true)]
+        command: Vec<OsString>,
+    },
 }
 
 #[tokio::main]
@@ -51,8 +97,8 @@ async fn main() -> Result<()> {
     // Logging (Respects RUST_LOG environment variable)
     // ------------------------------------------------------------
     tracing_subscriber::registry()
-        .with(fmt::layer().with_target(false).with_writer(std::io::stdout))
-        .with(EnvFilter::from_default_env())
+        .with(fmt::layer().with_target(false).with_writer(std::io::stdout).with_filter(EnvFilter::from_default_env()))
+        .with(pow::solver_metrics())
         .init();
 
     let args = Args::parse();
@@ -64,7 +110,13 @@ async fn main() -> Result<()> {
     if cfg.strict_mode {
         info!("Strict zero-trust mode enabled (mTLS + Secure Heap)");
 
-        if let Err(e) = hardening::apply_protections(true) {
+        if args.testing_network {
+            panic!("ABORT: --testing-network is not allowed in strict mode");
+        }
+
+        // `run` forks its program; managed pluggable transports are relaunched by Arti at will
+        let spawns_children = args.command.is_some() || bridges::spawns_transports(&cfg);
+        if let Err(e) = hardening::apply_protections(true, spawns_children) {
             error!("Security hardening failed: {e}");
             panic!("ABORT: strict mode requires hardened kernel");
         }
@@ -94,6 +146,20 @@ async fn main() -> Result<()> {
         fs::set_permissions(&cfg.tor_cache_dir, fs::Permissions::from_mode(0o700))?;
     }
 
+    // ------------------------------------------------------------
+    // TLS preflight and policy lists (fail fast, before the slow Tor bootstrap)
+    // ------------------------------------------------------------
+    // `run` serves only its own namespace, in plaintext, so it needs no TLS material
+    let tls_material = match args.command {
+        Some(Command::Run { .. }) => None,
+        None => Some(tls::preflight(&cfg).context("TLS preflight failed")?),
+    };
+    let domain_filter = filter::DomainFilter::load(&cfg).context("Failed to load domain policy")?;
+    let rewrites = mapaddress::AddressRewriter::load(&cfg).context("Failed to load MapAddress table")?;
+    let chains = chain::ProxyChains::load(&cfg).context("Failed to load proxy chains")?;
+    let onion_services = onion::prepare(&cfg).context("Onion service configuration invalid")?;
+    let client_auth_keys = clientauth::load(&cfg).context("Onion client authorization keys invalid")?;
+
     // ------------------------------------------------------------
     // Tor configuration
     // ------------------------------------------------------------
@@ -107,6 +173,22 @@ async fn main() -> Result<()> {
         .permissions()
         .dangerously_trust_everyone(); // Safe because directory is in a locked-down container tmpfs
 
+    // Keys live in memory only: onion service identities are generated per boot
+    // or inserted from key files (as are client auth keys), never written to the state dir
+    tor_cfg
+        .storage()
+        .keystore()
+        .primary()
+        .kind(ExplicitOrAuto::Explicit(ArtiKeystoreKind::Ephemeral));
+
+    bridges::configure(&cfg, &mut tor_cfg).context("Bridge configuration invalid")?;
+
+    tuning::configure(&cfg, &mut tor_cfg);
+    testnet::configure(&cfg, args.testing_network, &mut tor_cfg).context("Private network configuration invalid")?;
+
+    // Raw arti.toml settings on top, except where torrust keeps control
+    let tor_cfg = overlay::apply(&cfg, tor_cfg).context("Arti configuration overlay invalid")?;
+
     let tor_cfg = tor_cfg
         .build()
         .context("Failed to build Tor configuration")?;
@@ -116,12 +198,16 @@ async fn main() -> Result<()> {
     // ------------------------------------------------------------
     info!("Bootstrapping Tor");
 
-    let tor_client = TorClient::builder()
+    let runtime = upstream::runtime(&cfg).await.context("Upstream proxy configuration invalid")?;
+
+    let tor_client = TorClient::with_runtime(runtime)
         .config(tor_cfg)
         .create_bootstrapped()
         .await
         .context("Tor bootstrap failed")?;
 
+    clientauth::install(&tor_client, client_auth_keys)?;
+
     let tor_client = Arc::new(tor_client);
 
     // ------------------------------------------------------------
@@ -134,18 +220,108 @@ async fn main() -> Result<()> {
 
     info!("Tor ready. Starting network services");
 
+    // Isolation tokens are shared by every front-end so NEWNYM covers all
+    let proxy_state = Arc::new(proxy::ProxyState::new(tor_client.clone(), cfg.clone(), domain_filter, rewrites, chains));
+
+    // ------------------------------------------------------------
+    // `torrust run -- <cmd>`: serve one sandboxed program, then exit with its status
+    // ------------------------------------------------------------
+    if let Some(Command::Run { command }) = args.command {
+        let code = sandbox::run(proxy_state, command).await?;
+        info!("Sandboxed program exited ({code})");
+        std::process::exit(code);
+    }
+
+    let tls_material = tls_material.expect("TLS preflight runs for every server mode");
+    let router = Arc::new(router::Router::new(&cfg, &tls_material).context("Failed to build TLS routes")?);
+
     // ------------------------------------------------------------
     // SOCKS proxy (primary interface)
     // ------------------------------------------------------------
-    {
-        let tor = tor_client.clone();
-        let cfg = cfg.clone();
+    if cfg.frontend_tcp {
+        let state = proxy_state.clone();
+        let router = router.clone();
 
         tokio::spawn(async move {
-            if let Err(e) = proxy::start_socks_server(tor, cfg).await {
+            if let Err(e) = proxy::start_socks_server(state, router).await {
                 error!("SOCKS server terminated: {e}");
             }
         });
+    } else {
+        info!("TCP front-end disabled; reachable only as an onion service");
+    }
+
+    // ------------------------------------------------------------
+    // DNS-over-TLS (same mTLS trust as SOCKS)
+    // ------------------------------------------------------------
+    // No inbound port at all when the front-end is onion-only (use a `dns` TLS route)
+    if let Some(port) = cfg.dot_port.filter(|_| cfg.frontend_tcp) {
+        let state = proxy_state.clone();
+        let material = tls_material.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = dns::start_dot_server(state, material, port).await {
+                error!("DoT server terminated: {e}");
+            }
+        });
+    }
+
+    // ------------------------------------------------------------
+    // Transparent proxy for REDIRECTed traffic (opt-in, never public)
+    // ------------------------------------------------------------
+    if let Some(bind_addr) = cfg.trans_bind {
+        let state = proxy_state.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = transparent::start_trans_server(state, bind_addr).await {
+                error!("Transparent proxy terminated: {e}");
+            }
+        });
+    }
+
+    // ------------------------------------------------------------
+    // SIGHUP: reload the MapAddress table (previous table kept on error)
+    // ------------------------------------------------------------
+    #[cfg(unix)]
+    if cfg.mapaddress_file.is_some() {
+        let state = proxy_state.clone();
+
+        tokio::spawn(async move {
+            let mut hangup = match signal::unix::signal(signal::unix::SignalKind::hangup()) {
+                Ok(hangup) => hangup,
+                Err(e) => {
+                    error!("Cannot listen for SIGHUP: {e}");
+                    return;
+                }
+            };
+
+            while hangup.recv().await.is_some() {
+                match state.rewrites.reload() {
+                    Ok(rules) => info!("MapAddress reloaded: {rules} rewrite rules"),
+                    Err(e) => error!("MapAddress reload failed, keeping previous table: {e:#}"),
+                }
+            }
+        });
+    }
+
+    // ------------------------------------------------------------
+    // Onion services: hosted local services and the onion front-end
+    // ------------------------------------------------------------
+    onion::launch(&proxy_state, &router, onion_services).context("Failed to start onion services")?;
+
+    tls::spawn_expiry_monitor(tls_material, cfg.tls_expiry_warn_days, cfg.tls_expiry_check_hours);
+
+    // ------------------------------------------------------------
+    // Local DNS for stub resolvers (opt-in, never public)
+    // ------------------------------------------------------------
+    if let Some(bind_addr) = cfg.dns_bind {
+        let state = proxy_state.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = dns::start_dns_server(state, bind_addr).await {
+                error!("DNS server terminated: {e}");
+            }
+        });
     }
 
     // ------------------------------------------------------------
@@ -0,0 +1,120 @@
+// src/mapaddress.rs
+//
+// Destination rewrite table (Tor's MapAddress), e.g. clearnet names to their
+// onion mirrors. Loaded from MAPADDRESS_FILE and reloaded on SIGHUP; a bad
+// reload keeps the previous table.
+//
+//   duckduckgo.com        duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion
+//   *.example.org         mirror.example.net      every subdomain to one host
+//   *.example.com         *.example.onion-mirror.net   keep the subdomain part
+//
+// Lines may also carry torrc's `MapAddress` prefix; `#` starts a comment.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use crate::config::Config;
+use crate::hostname;
+
+enum Target {
+    Host(String),
+    /// Replace the matched suffix, keeping any subdomain labels
+    Suffix(String),
+}
+
+#[derive(Default)]
+struct Table {
+    exact: HashMap<String, String>,
+    /// Longest suffix first, so the most specific wildcard wins
+    wildcards: Vec<(String, Target)>,
+}
+
+pub struct AddressRewriter {
+    path: Option<PathBuf>,
+    table: RwLock<Arc<Table>>,
+}
+
+impl AddressRewriter {
+    pub fn load(cfg: &Config) -> Result<Self> {
+        let table = match &cfg.mapaddress_file {
+            Some(path) => {
+                let table = parse_file(path)?;
+                tracing::info!("MapAddress: {} rewrite rules loaded", table.exact.len() + table.wildcards.len());
+                table
+            }
+            None => Table::default(),
+        };
+
+        Ok(AddressRewriter {
+            path: cfg.mapaddress_file.clone(),
+            table: RwLock::new(Arc::new(table)),
+        })
+    }
+
+    /// Re-reads the file, returning the number of rules now active.
+    pub fn reload(&self) -> Result<usize> {
+        let Some(path) = &self.path else { return Ok(0) };
+        let table = parse_file(path)?;
+        let rules = table.exact.len() + table.wildcards.len();
+        *self.table.write().unwrap() = Arc::new(table);
+        Ok(rules)
+    }
+
+    /// Rewritten destination for a canonical name, if any rule applies.
+    pub fn rewrite(&self, name: &str) -> Option<String> {
+        let table = self.table.read().unwrap().clone();
+
+        if let Some(target) = table.exact.get(name) {
+            return Some(target.clone());
+        }
+
+        table.wildcards.iter().find_map(|(suffix, target)| {
+            let prefix = if name == suffix {
+                ""
+            } else {
+                name.strip_suffix(suffix.as_str())?.strip_suffix('.')?
+            };
+            Some(match target {
+                Target::Host(host) => host.clone(),
+                Target::Suffix(to) if prefix.is_empty() => to.clone(),
+                Target::Suffix(to) => format!("{}.{}", prefix, to),
+            })
+        })
+    }
+}
+
+fn parse_file(path: &Path) -> Result<Table> {
+    let text = fs::read_to_string(path).with_context(|| format!("Cannot read MapAddress file {:?}", path))?;
+    let mut table = Table::default();
+
+    for (lineno, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let line = line.strip_prefix("MapAddress").map(str::trim_start).unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [from, to] = fields[..] else {
+            anyhow::bail!("{:?} line {}: expected `from to`", path, lineno + 1);
+        };
+        let canonical = |name: &str| {
+            hostname::canonicalize(name).map_err(|e| anyhow::anyhow!("{:?} line {}: '{}' is invalid ({:?})", path, lineno + 1, name, e))
+        };
+
+        match (from.strip_prefix("*."), to.strip_prefix("*.")) {
+            (Some(from), Some(to)) => table.wildcards.push((canonical(from)?, Target::Suffix(canonical(to)?))),
+            (Some(from), None) => table.wildcards.push((canonical(from)?, Target::Host(canonical(to)?))),
+            (None, None) => {
+                table.exact.insert(canonical(from)?, canonical(to)?);
+            }
+            (None, Some(_)) => anyhow::bail!("{:?} line {}: a wildcard target needs a wildcard source", path, lineno + 1),
+        }
+    }
+
+    table.wildcards.sort_by_key(|(suffix, _)| std::cmp::Reverse(suffix.len()));
+    Ok(table)
+}
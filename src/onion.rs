@@ -0,0 +1,256 @@
+// src/onion.rs
+//
+// Hosted onion services: each virtual port forwards to a local `host:port`.
+// The TLS front-end itself can also be published this way, so clients reach
+// the proxy over Tor and no inbound port is needed.
+// Identity keys are either generated per boot in Arti's in-memory keystore
+// (nothing reaches disk, like the rest of the tmpfs state) or decrypted from
+// a passphrase-protected PKCS#8 Ed25519 key so the address survives restarts.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::sync::Arc;
+
+use arti_client::config::onion_service::{OnionServiceConfig, OnionServiceConfigBuilder};
+use arti_client::config::CfgPath;
+use der::asn1::OctetStringRef;
+use der::Decode;
+use futures::StreamExt;
+use pkcs8::PrivateKeyInfo;
+use rustls_pki_types::PrivateKeyDer;
+use safelog::DisplayRedacted;
+use tokio::net::TcpStream;
+use tor_cell::relaycell::msg::{Connected, End, EndReason};
+use tor_hscrypto::pk::HsIdKeypair;
+use tor_hsservice::config::restricted_discovery::DirectoryKeyProviderBuilder;
+use tor_hsservice::{handle_rend_requests, StreamRequest};
+use tor_llcrypto::pk::ed25519;
+use tor_proto::client::stream::IncomingStreamRequest;
+use tor_rtcompat::Runtime;
+use zeroize::Zeroizing;
+
+use crate::config::Config;
+use crate::identity::{self, SecretKey};
+use crate::proxy::{relay, serve_tls, ProxyState};
+use crate::router::Router;
+
+const ID_ED25519: der::asn1::ObjectIdentifier = der::asn1::ObjectIdentifier::new_unwrap("1.3.101.112");
+
+/// Nickname of the service publishing the TLS front-end
+const FRONTEND_NICKNAME: &str = "torrust-frontend";
+
+enum Target {
+    /// Virtual port to local `host:port`
+    Forward(Vec<(u16, String)>),
+    /// The mTLS front-end, on this virtual port
+    Frontend(u16),
+}
+
+/// A configured service, validated and with its key decrypted, ready to launch
+pub struct PreparedService {
+    config: OnionServiceConfig,
+    key: Option<HsIdKeypair>,
+    target: Arc<Target>,
+}
+
+/// Validates every ONION_SERVICES entry (and the front-end service, when
+/// enabled) and decrypts persistent keys. Runs before Tor bootstraps so a
+/// bad key or passphrase fails fast.
+pub fn prepare(cfg: &Config) -> Result<Vec<PreparedService>> {
+    let needs_passphrase = cfg.onion_services.iter().any(|s| s.key.is_some())
+        || (cfg.frontend_onion && cfg.frontend_onion_key.is_some());
+    let passphrase = if needs_passphrase {
+        identity::read_passphrase_from(cfg.onion_key_passphrase_fd, cfg.onion_key_passphrase_file.as_deref())?
+            .context("Onion service key configured but no passphrase source (ONION_KEY_PASSPHRASE_FD / ONION_KEY_PASSPHRASE_FILE)")?
+    } else {
+        Zeroizing::new(String::new())
+    };
+
+    let mut prepared = Vec::new();
+
+    for service in &cfg.onion_services {
+        if service.nickname == FRONTEND_NICKNAME || cfg.onion_services.iter().filter(|s| s.nickname == service.nickname).count() > 1 {
+            anyhow::bail!("Onion service nickname '{}' is reserved or used twice", service.nickname);
+        }
+        let builder = service_builder(&service.nickname)?;
+        let key = service.key.as_deref().map(|path| load_key(&service.nickname, path, &passphrase)).transpose()?;
+
+        prepared.push(PreparedService {
+            config: build(builder, &service.nickname)?,
+            key,
+            target: Arc::new(Target::Forward(service.ports.clone())),
+        });
+    }
+
+    if cfg.frontend_onion {
+        let mut builder = service_builder(FRONTEND_NICKNAME)?;
+
+        // Restricted discovery: only listed clients can even find the introduction points
+        if let Some(dir) = &cfg.frontend_onion_clients {
+            let discovery = builder.restricted_discovery();
+            discovery.enabled(true);
+            discovery
+                .key_dirs()
+                .access()
+                .push(DirectoryKeyProviderBuilder::default().path(CfgPath::new(dir.to_string_lossy().into_owned())).clone());
+        }
+
+        let key = match &cfg.frontend_onion_key {
+            Some(path) => Some(load_key(FRONTEND_NICKNAME, path, &passphrase)?),
+            None => {
+                tracing::warn!("FRONTEND_ONION_KEY not set: the front-end onion address changes on every restart");
+                None
+            }
+        };
+
+        prepared.push(PreparedService {
+            config: build(builder, FRONTEND_NICKNAME)?,
+            key,
+            target: Arc::new(Target::Frontend(cfg.socks_port)),
+        });
+    }
+
+    Ok(prepared)
+}
+
+fn service_builder(nickname: &str) -> Result<OnionServiceConfigBuilder> {
+    let nickname = nickname
+        .parse()
+        .with_context(|| format!("Invalid onion service nickname '{}'", nickname))?;
+    let mut builder = OnionServiceConfigBuilder::default();
+    builder.nickname(nickname);
+    Ok(builder)
+}
+
+fn build(builder: OnionServiceConfigBuilder, nickname: &str) -> Result<OnionServiceConfig> {
+    builder.build().with_context(|| format!("Invalid onion service '{}'", nickname))
+}
+
+/// Publishes the services and serves their streams until shutdown.
+pub fn launch<R: Runtime>(state: &Arc<ProxyState<R>>, router: &Arc<Router>, services: Vec<PreparedService>) -> Result<()> {
+    for PreparedService { config, key, target } in services {
+        let nickname = config.nickname().to_string();
+
+        let launched = match key {
+            Some(key) => state
+                .tor
+                .launch_onion_service_with_hsid(config, key)
+                .map(|l| l.map(|(service, requests)| (service, requests.boxed()))),
+            None => state
+                .tor
+                .launch_onion_service(config)
+                .map(|l| l.map(|(service, requests)| (service, requests.boxed()))),
+        }
+        .with_context(|| format!("Failed to launch onion service '{}'", nickname))?;
+
+        let Some((service, rend_requests)) = launched else { continue };
+
+        match service.onion_address() {
+            Some(address) => tracing::info!("Onion service '{}' at {}", nickname, address.display_unredacted()),
+            None => tracing::warn!("Onion service '{}' launched without a known address", nickname),
+        }
+
+        let state = state.clone();
+        let router = router.clone();
+
+        tokio::spawn(async move {
+            // The service stops when its handle is dropped
+            let _service = service;
+            let mut streams = handle_rend_requests(rend_requests).boxed();
+
+            while let Some(request) = streams.next().await {
+                let target = target.clone();
+                let nickname = nickname.clone();
+                let state = state.clone();
+                let router = router.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve_stream(request, &target, state, router).await {
+                        tracing::debug!("Onion service '{}' stream ended: {:#}", nickname, e);
+                    }
+                });
+            }
+        });
+    }
+
+    Ok(())
+}
+
+async fn serve_stream<R: Runtime>(
+    request: StreamRequest,
+    target: &Target,
+    state: Arc<ProxyState<R>>,
+    router: Arc<Router>,
+) -> Result<()> {
+    let port = match request.request() {
+        IncomingStreamRequest::Begin(begin) => begin.port(),
+        _ => {
+            request.shutdown_circuit()?;
+            return Ok(());
+        }
+    };
+
+    // Unknown ports tear down the circuit, as C Tor does, so scans learn nothing per port
+    match target {
+        Target::Forward(ports) => {
+            let Some((_, target)) = ports.iter().find(|(virtual_port, _)| *virtual_port == port) else {
+                request.shutdown_circuit()?;
+                return Ok(());
+            };
+
+            let local = match TcpStream::connect(target).await {
+                Ok(local) => local,
+                Err(e) => {
+                    tracing::warn!("Onion service target {} unreachable: {}", target, e);
+                    request.reject(End::new_with_reason(EndReason::DONE)).await?;
+                    return Ok(());
+                }
+            };
+
+            let tor_stream = request.accept(Connected::new_empty()).await?;
+            relay(local, tor_stream).await;
+        }
+        Target::Frontend(virtual_port) => {
+            if port != *virtual_port {
+                request.shutdown_circuit()?;
+                return Ok(());
+            }
+
+            // Same routed mTLS handshake as the TCP listener; clients stay anonymous here
+            let tor_stream = request.accept(Connected::new_empty()).await?;
+            serve_tls(tor_stream, "onion service".to_string(), router, state).await;
+        }
+    }
+    Ok(())
+}
+
+fn load_key(nickname: &str, path: &std::path::Path, passphrase: &str) -> Result<HsIdKeypair> {
+    decrypt_key(path, passphrase)
+        .with_context(|| format!("Cannot load key for onion service '{}' ({})", nickname, path.display()))
+}
+
+/// Reads an Ed25519 identity key stored as an ENCRYPTED PRIVATE KEY (PKCS#8, PBES2).
+fn decrypt_key(path: &std::path::Path, passphrase: &str) -> Result<HsIdKeypair> {
+    let pem = Zeroizing::new(fs::read(path)?);
+    // Wiped on drop, including the early returns below
+    let key = SecretKey::from(identity::decrypt_pkcs8_pem(&pem, passphrase)?);
+    let PrivateKeyDer::Pkcs8(der) = key.der() else {
+        anyhow::bail!("Not a PKCS#8 key");
+    };
+
+    let info = PrivateKeyInfo::try_from(der.secret_pkcs8_der()).context("Malformed PKCS#8 key")?;
+    if info.algorithm.oid != ID_ED25519 {
+        anyhow::bail!("Onion service keys must be Ed25519 (found {})", info.algorithm.oid);
+    }
+
+    let seed = OctetStringRef::from_der(info.private_key).context("Malformed Ed25519 private key")?;
+    let seed: Zeroizing<[u8; 32]> = Zeroizing::new(
+        seed.as_bytes()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Ed25519 private key is not 32 bytes"))?,
+    );
+    // The seed is copied out; the decrypted PKCS#8 document is no longer needed
+    drop(key);
+
+    let keypair = ed25519::Keypair::from_bytes(&seed);
+    Ok(HsIdKeypair::from(ed25519::ExpandedKeypair::from(&keypair)))
+}
@@ -0,0 +1,149 @@
+// src/overlay.rs
+//
+// Raw Arti configuration (ARTI_CONFIG_FILE): an arti.toml fragment for the
+// settings torrust has no variable for. It is merged with Arti's own loader on
+// top of everything torrust configured, so the file wins over torrust's
+// defaults and tuning. Unknown keys are an error rather than the usual
+// warning, since a typo would otherwise leave a setting silently at default.
+//
+// Some sections stay torrust's whatever the file says, each for a reason:
+//   storage      tmpfs paths and the in-memory keystore (nothing on disk)
+//   bridges      PT binaries decide the strict-mode process policy, and the
+//                upstream proxy checks run on torrust's own bridge config
+//   tor_network  a different set of authorities is a different Tor network
+//   override_net_params, address_filter, path_rules.ipv{4,6}_subnet_family_prefix
+//                relaxations reserved for --testing-network (see testnet.rs)
+// Keys the file sets there are logged and dropped.
+
+use anyhow::{Context, Result};
+use std::fs;
+
+use arti_client::config::{ConfigBuildError, ConfigurationSource, ConfigurationSources, TorClientConfigBuilder};
+use serde::{Deserialize, Deserializer};
+use tor_config::load::{Builder, TopLevel};
+use tor_config::sources::MustRead;
+
+use crate::config::Config;
+
+/// Sections and keys the file may not change
+const TORRUST_OWNED: &[&str] = &[
+    "storage",
+    "bridges",
+    "tor_network",
+    "override_net_params",
+    "address_filter",
+    "path_rules.ipv4_subnet_family_prefix",
+    "path_rules.ipv6_subnet_family_prefix",
+];
+
+/// The merged builder, so torrust can still adjust it before building
+struct Overlay(TorClientConfigBuilder);
+
+struct OverlayBuilder(TorClientConfigBuilder);
+
+impl TopLevel for Overlay {
+    type Builder = OverlayBuilder;
+}
+
+impl<'de> Deserialize<'de> for OverlayBuilder {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        TorClientConfigBuilder::deserialize(deserializer).map(OverlayBuilder)
+    }
+}
+
+impl Builder for OverlayBuilder {
+    type Built = Overlay;
+
+    fn build(&self) -> Result<Overlay, ConfigBuildError> {
+        // Validate the merge as a whole; torrust's own sections are restored afterwards
+        self.0.build()?;
+        Ok(Overlay(self.0.clone()))
+    }
+}
+
+/// Merges ARTI_CONFIG_FILE over torrust's configuration, if one is set.
+pub fn apply(cfg: &Config, mut base: TorClientConfigBuilder) -> Result<TorClientConfigBuilder> {
+    let Some(path) = &cfg.arti_config_file else { return Ok(base) };
+
+    let text = fs::read_to_string(path).with_context(|| format!("Cannot read ARTI_CONFIG_FILE {:?}", path))?;
+    let fragment: toml::Table = toml::from_str(&text).with_context(|| format!("{:?} is not valid TOML", path))?;
+
+    // torrust's settings go in as the first layer, so the file overrides only what it names
+    let mut defaults = serde_json::to_value(&base).context("Cannot serialise torrust's Tor configuration")?;
+    drop_unset(&mut defaults);
+    let defaults = toml::to_string(&defaults).context("Cannot serialise torrust's Tor configuration")?;
+    let mut sources = ConfigurationSources::new_empty();
+    sources.push_source(ConfigurationSource::from_verbatim(defaults), MustRead::MustRead);
+    sources.push_source(ConfigurationSource::from_path(path), MustRead::MustRead);
+    let tree = sources.load().with_context(|| format!("Cannot load {:?}", path))?;
+
+    let results = tor_config::resolve_return_results::<Overlay>(tree)
+        .with_context(|| format!("{:?} is not a valid Arti configuration", path))?;
+    if !results.unrecognized.is_empty() {
+        let keys: Vec<String> = results.unrecognized.iter().map(ToString::to_string).collect();
+        anyhow::bail!("{:?} has unknown keys: {}", path, keys.join(", "));
+    }
+    for key in &results.deprecated {
+        tracing::warn!("ARTI_CONFIG_FILE: {} is deprecated", key);
+    }
+
+    let mut merged = results.value.0;
+    for owned in TORRUST_OWNED {
+        let mut keys = Vec::new();
+        if let Some(value) = lookup(&fragment, owned) {
+            leaf_keys(owned, value, &mut keys);
+        }
+        for key in keys {
+            tracing::warn!("ARTI_CONFIG_FILE sets {}, which torrust controls; ignored", key);
+        }
+    }
+
+    *merged.storage() = base.storage().clone();
+    *merged.bridges() = base.bridges().clone();
+    *merged.tor_network() = base.tor_network().clone();
+    *merged.override_net_params() = base.override_net_params().clone();
+    *merged.address_filter() = base.address_filter().clone();
+
+    // Only the subnet prefixes of path_rules are torrust's; the rest stays the file's
+    let mut path_rules = base.path_rules().clone();
+    *path_rules.reachable_addrs() = merged.path_rules().reachable_addrs().clone();
+    *path_rules.long_lived_ports() = merged.path_rules().long_lived_ports().clone();
+    *merged.path_rules() = path_rules;
+
+    tracing::info!("Arti configuration overlay {:?} applied", path);
+    Ok(merged)
+}
+
+/// Removes the nulls that unset builder fields serialise to, which TOML cannot hold
+fn drop_unset(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|_, v| !v.is_null());
+            map.values_mut().for_each(drop_unset);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(drop_unset),
+        _ => {}
+    }
+}
+
+/// The value at a dotted path such as `path_rules.ipv4_subnet_family_prefix`
+fn lookup<'a>(table: &'a toml::Table, path: &str) -> Option<&'a toml::Value> {
+    let (first, rest) = path.split_once('.').map_or((path, None), |(a, b)| (a, Some(b)));
+    let value = table.get(first)?;
+    match rest {
+        Some(rest) => lookup(value.as_table()?, rest),
+        None => Some(value),
+    }
+}
+
+/// Dotted paths of every value under `value`, for the override warnings
+fn leaf_keys(prefix: &str, value: &toml::Value, out: &mut Vec<String>) {
+    match value {
+        toml::Value::Table(table) if !table.is_empty() => {
+            for (key, value) in table {
+                leaf_keys(&format!("{}.{}", prefix, key), value, out);
+            }
+        }
+        _ => out.push(prefix.to_string()),
+    }
+}
@@ -0,0 +1,87 @@
+// src/policy.rs
+//
+// Destination policy, checked before any Tor stream is opened.
+// Onion-only mode runs first, then the ordered DEST_POLICY rules; the first
+// matching rule decides and an unmatched destination is accepted.
+
+use std::net::IpAddr;
+
+use tracing::Level;
+
+use crate::config::{cidr_contains, Config, DestAction, DestRule};
+
+/// Whether `host:port` may be reached. Rejections are logged at the
+/// configured level with the reason.
+pub fn permits(cfg: &Config, host: &str, port: u16) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let onion = host.ends_with(".onion");
+
+    if cfg.onion_only && !onion {
+        log_rejection(cfg.dest_log_level, "onion-only mode", &host, port);
+        return false;
+    }
+
+    let addr = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().ok();
+
+    for (i, rule) in cfg.dest_rules.iter().enumerate() {
+        if !rule_matches(rule, &host, addr, port, onion) {
+            continue;
+        }
+        if rule.action == DestAction::Reject {
+            let reason = format!("rule #{} ({})", i + 1, rule.text);
+            log_rejection(cfg.dest_log_level, &reason, &host, port);
+            return false;
+        }
+        return true;
+    }
+
+    true
+}
+
+/// Whether a rule's conditions cover `host:port`, for tables that borrow the
+/// DEST_POLICY syntax (CHAIN_RULES).
+pub fn matches(rule: &DestRule, host: &str, port: u16) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let addr = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().ok();
+    rule_matches(rule, &host, addr, port, host.ends_with(".onion"))
+}
+
+fn rule_matches(rule: &DestRule, host: &str, addr: Option<IpAddr>, port: u16, onion: bool) -> bool {
+    if let Some(want) = rule.onion {
+        if want != onion {
+            return false;
+        }
+    }
+
+    if let Some((lo, hi)) = rule.ports {
+        if port < lo || port > hi {
+            return false;
+        }
+    }
+
+    if let Some(suffix) = &rule.suffix {
+        let under = host.strip_suffix(suffix.as_str()).is_some_and(|rest| rest.is_empty() || rest.ends_with('.'));
+        if !under {
+            return false;
+        }
+    }
+
+    if let Some((net, prefix)) = rule.cidr {
+        if !addr.is_some_and(|a| cidr_contains(net, prefix, a)) {
+            return false;
+        }
+    }
+
+    true
+}
+
+fn log_rejection(level: Option<Level>, reason: &str, host: &str, port: u16) {
+    match level {
+        Some(Level::ERROR) => tracing::error!("Destination rejected by {}: {}:{}", reason, host, port),
+        Some(Level::WARN) => tracing::warn!("Destination rejected by {}: {}:{}", reason, host, port),
+        Some(Level::INFO) => tracing::info!("Destination rejected by {}: {}:{}", reason, host, port),
+        Some(Level::DEBUG) => tracing::debug!("Destination rejected by {}: {}:{}", reason, host, port),
+        Some(_) => tracing::trace!("Destination rejected by {}: {}:{}", reason, host, port),
+        None => {}
+    }
+}
@@ -0,0 +1,173 @@
+// src/pow.rs
+//
+// Client-side proof of work for onion services under DoS (proposal 327).
+// Arti solves each puzzle on a dedicated thread, never on the async workers,
+// and raises the effort after every failed introduction up to its fixed
+// ceiling of 10000. Torrust bounds the rest:
+//   - ONION_MAX_CONNECTS: onion connects in flight (PoW or not), a backstop on solver threads
+//   - ONION_INTRO_ATTEMPTS: introductions per connect, and so effort escalations
+//   - ONION_POW_MAX_SECS: time per connect, and so CPU per solve
+// Per-solve effort and duration are taken from Arti's solver events, logged
+// at debug and summarised in the admin /status. Arti has no API for these, so
+// the event text is parsed; the test below pins the format, and an event that
+// no longer parses is reported once instead of being counted as zeros.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Target of the "solve complete" debug event in tor-hsclient
+const SOLVER_TARGET: &str = "tor_hsclient::pow::v1";
+
+struct SolveStats {
+    solves: AtomicU64,
+    failures: AtomicU64,
+    total_ms: AtomicU64,
+    last_effort: AtomicU32,
+    max_effort: AtomicU32,
+}
+
+static STATS: SolveStats = SolveStats {
+    solves: AtomicU64::new(0),
+    failures: AtomicU64::new(0),
+    total_ms: AtomicU64::new(0),
+    last_effort: AtomicU32::new(0),
+    max_effort: AtomicU32::new(0),
+};
+
+/// Set once an unparseable solver event has been reported
+static FORMAT_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Layer recording Arti's PoW solves. Carries its own filter so the
+/// solver events reach it whatever RUST_LOG says.
+pub fn solver_metrics<S: Subscriber + for<'a> LookupSpan<'a>>() -> impl Layer<S> {
+    SolverMetrics.with_filter(Targets::new().with_target(SOLVER_TARGET, Level::DEBUG))
+}
+
+/// `"pow_solves":..` fields for the admin status document
+pub fn status_fields() -> String {
+    format!(
+        "\"pow_solves\":{},\"pow_failures\":{},\"pow_solve_ms\":{},\"pow_last_effort\":{},\"pow_max_effort\":{}",
+        STATS.solves.load(Ordering::Relaxed),
+        STATS.failures.load(Ordering::Relaxed),
+        STATS.total_ms.load(Ordering::Relaxed),
+        STATS.last_effort.load(Ordering::Relaxed),
+        STATS.max_effort.load(Ordering::Relaxed),
+    )
+}
+
+struct SolverMetrics;
+
+impl<S: Subscriber> Layer<S> for SolverMetrics {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut message = MessageVisitor(String::new());
+        event.record(&mut message);
+
+        if !message.0.starts_with("solve complete") {
+            return;
+        }
+        let Some(Solve { succeeded, effort, millis }) = parse_solve(&message.0) else {
+            if !FORMAT_WARNED.swap(true, Ordering::Relaxed) {
+                tracing::warn!("Unrecognised Arti PoW solver event, PoW metrics are unavailable: {}", message.0);
+            }
+            return;
+        };
+
+        if succeeded {
+            STATS.solves.fetch_add(1, Ordering::Relaxed);
+        } else {
+            STATS.failures.fetch_add(1, Ordering::Relaxed);
+        }
+        STATS.total_ms.fetch_add(millis, Ordering::Relaxed);
+        STATS.last_effort.store(effort, Ordering::Relaxed);
+        STATS.max_effort.fetch_max(effort, Ordering::Relaxed);
+
+        tracing::debug!(
+            "Onion PoW {} at effort {} in {} ms",
+            if succeeded { "solved" } else { "failed" },
+            effort,
+            millis
+        );
+    }
+}
+
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Solve {
+    succeeded: bool,
+    effort: u32,
+    millis: u64,
+}
+
+/// "solve complete, Ok(()) Effort(400) duration=812ms (ratio: 2.03 ms)"
+fn parse_solve(message: &str) -> Option<Solve> {
+    let rest = message.strip_prefix("solve complete, ")?;
+    let succeeded = if rest.starts_with("Ok(") {
+        true
+    } else if rest.starts_with("Err(") {
+        false
+    } else {
+        return None;
+    };
+    Some(Solve {
+        succeeded,
+        effort: between(rest, "Effort(", ")")?.parse().ok()?,
+        millis: between(rest, "duration=", "ms")?.parse().ok()?,
+    })
+}
+
+fn between<'a>(text: &'a str, start: &str, end: &str) -> Option<&'a str> {
+    let rest = &text[text.find(start)? + start.len()..];
+    Some(&rest[..rest.find(end)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tor_hscrypto::pow::v1::Effort;
+
+    /// Same format string and argument types as the "solve complete" event in
+    /// tor-hsclient's `HsPowClientV1::solve`. Update both when Arti changes it.
+    fn solve_event(result: Result<(), &str>, effort: u32, millis: u128) -> String {
+        let effort = Effort::from(effort);
+        format!(
+            "solve complete, {:?} {:?} duration={}ms (ratio: {} ms)",
+            result,
+            effort,
+            millis,
+            (millis as f32) / (*effort.as_ref() as f32),
+        )
+    }
+
+    #[test]
+    fn parses_solver_events() {
+        assert_eq!(
+            parse_solve(&solve_event(Ok(()), 400, 812)),
+            Some(Solve { succeeded: true, effort: 400, millis: 812 })
+        );
+        assert_eq!(
+            parse_solve(&solve_event(Err("SolverDisconnected"), 10000, 0)),
+            Some(Solve { succeeded: false, effort: 10000, millis: 0 })
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        assert_eq!(parse_solve("solve complete, done in 812ms"), None);
+        assert_eq!(parse_solve("solve complete, Ok(()) effort=400 duration=812ms"), None);
+    }
+}
@@ -2,66 +2,114 @@
 use anyhow::{Context, Result};
 use std::collections::HashMap;
 use std::collections::hash_map::DefaultHasher;
-use std::fs::File;
+use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::io::BufReader;
 use std::net::{IpAddr, SocketAddr};
 use std::sync::{Arc, Mutex};
 
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
 use tokio::time::{timeout, Duration};
 
-use arti_client::{DataStream, StreamPrefs, TorClient};
+use arti_client::{DataStream, ErrorKind, HasKind, StreamPrefs, TorClient};
 use arti_client::isolation::IsolationToken;
 use tor_rtcompat::Runtime;
 use zeroize::Zeroize;
 
-use tokio_rustls::rustls::ServerConfig;
-use tokio_rustls::rustls::server::WebPkiClientVerifier;
-use tokio_rustls::rustls::RootCertStore;
-use tokio_rustls::TlsAcceptor;
-use rustls_pemfile::{certs, private_key};
-
-use crate::config::Config;
-
-type IsolationMap = Arc<Mutex<HashMap<u64, IsolationToken>>>;
-
-pub async fn start_socks_server<R: Runtime>(
-    tor: Arc<TorClient<R>>,
-    cfg: Config,
-) -> Result<()> {
-    // 1. Load Server Cert and Key
-    let cert_file = File::open(&cfg.tls_cert_path).context("Failed to open cert")?;
-    let key_file = File::open(&cfg.tls_key_path).context("Failed to open key")?;
-    let certs_vec: Vec<_> = certs(&mut BufReader::new(cert_file)).filter_map(Result::ok).collect();
-    let key = private_key(&mut BufReader::new(key_file))?.context("Invalid private key")?;
-
-    // 2. Load the CA Certificate for mTLS verification
-    let ca_file = File::open(&cfg.tls_client_ca_path).context("Failed to open CA cert")?;
-    let ca_certs: Vec<_> = certs(&mut BufReader::new(ca_file)).filter_map(Result::ok).collect();
-    
-    let mut roots = RootCertStore::empty();
-    for cert in ca_certs {
-        roots.add(cert).context("Failed to add CA cert to trust roots")?;
+use tokio_rustls::rustls::server::Acceptor;
+use tokio_rustls::LazyConfigAcceptor;
+
+use crate::automap::AddressMap;
+use crate::chain::ProxyChains;
+use crate::config::{Config, Service};
+use crate::filter::DomainFilter;
+use crate::hostname::HostError;
+use crate::mapaddress::AddressRewriter;
+use crate::resolve::{self, DnsCache};
+use crate::router::{Router, Target};
+use crate::tunnel::TunnelError;
+
+/// Isolation-token cache bound; cleared wholesale when exceeded
+const MAX_ISOLATION_KEYS: usize = 1000;
+
+/// SOCKS commands: CONNECT, plus Tor's RESOLVE extensions (socks-extensions.txt §2)
+const CMD_CONNECT: u8 = 0x01;
+const CMD_RESOLVE: u8 = 0xF0;
+const CMD_RESOLVE_PTR: u8 = 0xF1;
+
+/// State shared by every connection accepted on the TLS front-end
+pub struct ProxyState<R: Runtime> {
+    pub tor: Arc<TorClient<R>>,
+    pub cfg: Config,
+    pub dns_cache: DnsCache,
+    pub automap: AddressMap,
+    pub filter: DomainFilter,
+    pub rewrites: AddressRewriter,
+    pub chains: ProxyChains,
+    /// Bounds onion connects in flight, PoW or not, and with them PoW solver threads
+    onion_connects: Semaphore,
+    isolation_map: Mutex<HashMap<u64, IsolationToken>>,
+    default_token: Mutex<IsolationToken>,
+}
+
+impl<R: Runtime> ProxyState<R> {
+    pub fn new(
+        tor: Arc<TorClient<R>>,
+        cfg: Config,
+        filter: DomainFilter,
+        rewrites: AddressRewriter,
+        chains: ProxyChains,
+    ) -> Self {
+        ProxyState {
+            tor,
+            dns_cache: DnsCache::new(&cfg),
+            automap: AddressMap::new(&cfg),
+            filter,
+            rewrites,
+            chains,
+            onion_connects: Semaphore::new(cfg.onion_max_connects),
+            cfg,
+            isolation_map: Mutex::new(HashMap::new()),
+            default_token: Mutex::new(IsolationToken::new()),
+        }
     }
-    let client_verifier = WebPkiClientVerifier::builder(roots.into())
-        .build()
-        .context("Failed to build client verifier")?;
 
-    // 3. Enforce mTLS
-    let server_config = ServerConfig::builder()
-        .with_client_cert_verifier(client_verifier) // <-- The Cryptographic Bouncer
-        .with_single_cert(certs_vec, key)
-        .context("Failed to build TLS config")?;
+    /// Picks the isolation token for a stream: per credentials, per host
+    /// (auto-isolation), or the shared default.
+    pub fn isolation_token(&self, cred_hash: Option<u64>, host: &str) -> IsolationToken {
+        let key = match cred_hash {
+            Some(hash) => hash,
+            None if self.cfg.auto_isolate_domains => {
+                let mut hasher = DefaultHasher::new();
+                host.hash(&mut hasher);
+                hasher.finish()
+            }
+            None => return *self.default_token.lock().unwrap(),
+        };
 
-    let tls_acceptor = TlsAcceptor::from(Arc::new(server_config));
+        let mut map = self.isolation_map.lock().unwrap();
+        if map.len() > MAX_ISOLATION_KEYS { map.clear(); }
+        *map.entry(key).or_insert_with(IsolationToken::new)
+    }
 
-    let bind_addr = SocketAddr::from(([0, 0, 0, 0], cfg.socks_port));
-    let listener = TcpListener::bind(bind_addr).await.context("Failed to bind SOCKS listener")?;
+    /// Drops every isolation token so new streams build fresh circuits, and
+    /// forgets every answer resolved under the old identities.
+    pub fn newnym(&self) {
+        self.isolation_map.lock().unwrap().clear();
+        *self.default_token.lock().unwrap() = IsolationToken::new();
+        self.dns_cache.flush();
+        tracing::info!("NEWNYM: isolation tokens rotated, DNS cache flushed");
+    }
 
-    let isolation_map: IsolationMap = Arc::new(Mutex::new(HashMap::new()));
-    let default_token = IsolationToken::new();
+    pub fn isolation_keys(&self) -> usize {
+        self.isolation_map.lock().unwrap().len()
+    }
+}
+
+pub async fn start_socks_server<R: Runtime>(state: Arc<ProxyState<R>>, router: Arc<Router>) -> Result<()> {
+    let bind_addr = SocketAddr::from(([0, 0, 0, 0], state.cfg.socks_port));
+    let listener = TcpListener::bind(bind_addr).await.context("Failed to bind SOCKS listener")?;
 
     tracing::info!("mTLS SOCKS5 proxy listening on {}", bind_addr);
 
@@ -72,29 +120,83 @@ pub async fn start_socks_server<R: Runtime>(
             tracing::warn!("Failed to set TCP_NODELAY: {}", e);
         }
         
-        let tor = tor.clone();
-        let acceptor = tls_acceptor.clone();
-        let iso_map = isolation_map.clone();
-        let def_token = default_token.clone();
-        let auto_isolate = cfg.auto_isolate_domains; 
-
-        tokio::spawn(async move {
-            match acceptor.accept(socket).await {
-                Ok(tls_stream) => {
-                    let _ = handle_socks_connection(tls_stream, tor, iso_map, def_token, auto_isolate).await;
+        tokio::spawn(serve_tls(socket, peer_addr.to_string(), router.clone(), state.clone()));
+    }
+}
+
+/// Runs the routed TLS handshake on one front-end connection (a TCP socket
+/// or an onion service stream) and hands it to the selected service.
+pub(crate) async fn serve_tls<R: Runtime, S>(socket: S, peer: String, router: Arc<Router>, state: Arc<ProxyState<R>>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let start = match LazyConfigAcceptor::new(Acceptor::default(), socket).await {
+        Ok(start) => start,
+        Err(e) => {
+            tracing::warn!("TLS ClientHello unreadable (probe dropped from {}): {}", peer, e);
+            return;
+        }
+    };
+
+    let Some((target, server_config)) = router.select(&start.client_hello()) else {
+        tracing::warn!("No TLS route for ClientHello (probe dropped from {})", peer);
+        return;
+    };
+
+    match start.into_stream(server_config).await {
+        Ok(tls_stream) => {
+            let certs = tls_stream.get_ref().1.peer_certificates();
+            let authenticated = certs.is_some();
+            let peer_name = certs.and_then(crate::tls::peer_common_name);
+            dispatch(tls_stream, target, authenticated, peer_name, &peer, state).await;
+        }
+        Err(e) => tracing::warn!("mTLS handshake failed (Unauthorized probe dropped from {}): {}", peer, e),
+    }
+}
+
+/// Hands an established TLS stream to the service its route selected.
+async fn dispatch<R: Runtime, S>(
+    stream: S,
+    target: Target,
+    authenticated: bool,
+    peer_name: Option<String>,
+    peer: &str,
+    state: Arc<ProxyState<R>>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let service = match target {
+        Target::Service(service, _) if authenticated => service,
+        _ => {
+            match &state.cfg.decoy {
+                Some(decoy) => {
+                    tracing::debug!("No client certificate from {}, serving decoy", peer);
+                    if let Err(e) = crate::decoy::serve(stream, decoy).await {
+                        tracing::debug!("Decoy connection from {} ended: {}", peer, e);
+                    }
                 }
-                Err(e) => tracing::warn!("mTLS handshake failed (Unauthorized probe dropped from {}): {}", peer_addr, e),
+                None => tracing::warn!("Unauthenticated peer dropped from {}", peer),
             }
-        });
+            return;
+        }
+    };
+
+    let result = match service {
+        Service::Socks => handle_socks_connection(stream, state, peer_name).await,
+        Service::HttpConnect => crate::http_connect::handle_http_connect(stream, state, peer_name).await,
+        Service::Dns => crate::dns::handle_dns_stream(stream, state, peer_name).await,
+        Service::Admin => crate::admin::handle_admin(stream, state).await,
+    };
+
+    if let Err(e) = result {
+        tracing::debug!("{:?} session from {} ended: {:#}", service, peer, e);
     }
 }
 
-async fn handle_socks_connection<R: Runtime, S>(
+pub(crate) async fn handle_socks_connection<R: Runtime, S>(
     mut client: S,
-    tor: Arc<TorClient<R>>,
-    isolation_map: IsolationMap,
-    default_token: IsolationToken,
-    auto_isolate: bool,
+    state: Arc<ProxyState<R>>,
+    peer_name: Option<String>,
 ) -> Result<()>
 where
     S: AsyncReadExt + AsyncWriteExt + Unpin,
@@ -168,11 +270,12 @@ where
         let mut req = [0u8; 4];
         client.read_exact(&mut req).await.context("Failed to read SOCKS connect request")?;
 
-        if req[0] != 0x05 || req[1] != 0x01 {
+        if req[0] != 0x05 || ![CMD_CONNECT, CMD_RESOLVE, CMD_RESOLVE_PTR].contains(&req[1]) {
             req.zeroize();
             return Err(anyhow::anyhow!("Invalid SOCKS command"));
         }
         
+        let command = req[1];
         let addr_type = req[3];
         req.zeroize(); 
 
@@ -195,22 +298,37 @@ where
                 let mut p = [0u8; 2];
                 client.read_exact(&mut p).await?;
                 
-                let domain_str = String::from_utf8_lossy(&domain_bytes).into_owned();
                 let port_num = u16::from_be_bytes(p);
-                
-                domain_bytes.zeroize(); 
                 len.zeroize();
                 p.zeroize();
+
+                let domain_str = match String::from_utf8(domain_bytes) {
+                    Ok(domain_str) => domain_str,
+                    Err(e) => {
+                        e.into_bytes().zeroize();
+                        return Err(anyhow::anyhow!("SOCKS hostname is not valid UTF-8"));
+                    }
+                };
                 
                 (domain_str, port_num)
             }
+            0x04 => {
+                let mut addr = [0u8; 16];
+                client.read_exact(&mut addr).await?;
+                let mut p = [0u8; 2];
+                client.read_exact(&mut p).await?;
+                let res = (IpAddr::from(addr).to_string(), u16::from_be_bytes(p));
+                addr.zeroize();
+                p.zeroize();
+                res
+            }
             _ => return Err(anyhow::anyhow!("Unsupported SOCKS address type")),
         };
 
-        Ok((host, port, cred_hash))
+        Ok((command, host, port, cred_hash))
     }).await;
 
-    let (mut host, port, cred_hash) = match handshake_result {
+    let (command, mut host, port, cred_hash) = match handshake_result {
         Ok(Ok(res)) => res,
         Ok(Err(e)) => {
             tracing::warn!("SOCKS Error: {:#}", e);
@@ -224,40 +342,96 @@ where
         }
     };
 
-    let stream_token = match cred_hash {
-        Some(hash) => {
-            let mut map = isolation_map.lock().unwrap();
-            if map.len() > 1000 { map.clear(); }
-            map.entry(hash).or_insert_with(IsolationToken::new).clone()
+    match crate::hostname::canonicalize(&host) {
+        Ok(canonical) => {
+            host.zeroize();
+            host = canonical;
         }
-        None => {
-            if auto_isolate {
-                let mut hasher = DefaultHasher::new();
-                host.hash(&mut hasher);
-                let host_hash = hasher.finish();
-                
-                let mut map = isolation_map.lock().unwrap();
-                if map.len() > 1000 { map.clear(); }
-                map.entry(host_hash).or_insert_with(IsolationToken::new).clone()
-            } else {
-                default_token
+        Err(HostError::BadOnion) => {
+            tracing::warn!("Rejected malformed onion address (bad length, version or checksum)");
+            host.zeroize();
+            return reply_error(&mut client, 0xF6).await;
+        }
+        Err(HostError::Malformed) if crate::hostname::is_disguised_ip(&host) => {
+            tracing::warn!(
+                "Rejected SOCKS request for an IP address disguised as a name (port {}): the \
+                 application is resolving DNS locally, which leaks lookups. Configure it to send \
+                 hostnames (e.g. socks5h://)",
+                port
+            );
+            host.zeroize();
+            return reply_error(&mut client, 0x02).await;
+        }
+        Err(HostError::Malformed) => {
+            tracing::warn!("Rejected malformed SOCKS hostname");
+            host.zeroize();
+            return reply_failure(&mut client).await;
+        }
+    }
+
+    // Virtual addresses handed out by automap go back to their names
+    if !state.automap.rewrite(&mut host) {
+        tracing::warn!("SOCKS target is an unmapped virtual address");
+        host.zeroize();
+        return reply_error(&mut client, 0x04).await;
+    }
+
+    // SafeSocks: an IP literal means the application resolved DNS outside Tor
+    if state.cfg.safe_socks && command != CMD_RESOLVE_PTR && crate::hostname::is_ip_literal(&host) {
+        tracing::warn!(
+            "Rejected SOCKS request for a raw IP address (port {}): the application is resolving DNS \
+             locally, which leaks lookups. Configure it to send hostnames (e.g. socks5h://)",
+            port
+        );
+        host.zeroize();
+        return reply_error(&mut client, 0x02).await;
+    }
+
+    if command != CMD_RESOLVE_PTR && state.filter.is_blocked(peer_name.as_deref(), &host) {
+        tracing::debug!("Blocked by domain policy: {}", host);
+        host.zeroize();
+        return reply_error(&mut client, 0x02).await;
+    }
+
+    // Isolation follows the name the client asked for, not the MapAddress target
+    let stream_token = state.isolation_token(cred_hash, &host);
+
+    if command == CMD_CONNECT {
+        if let Some(target) = state.rewrites.rewrite(&host) {
+            tracing::debug!("MapAddress: {} -> {}", host, target);
+            host.zeroize();
+            host = target;
+
+            // A rewrite must not lead around the blocklists
+            if state.filter.is_blocked(peer_name.as_deref(), &host) {
+                tracing::debug!("Blocked by domain policy (MapAddress target): {}", host);
+                host.zeroize();
+                return reply_error(&mut client, 0x02).await;
             }
         }
-    };
 
-    tracing::debug!("Routing {}:{} through Tor...", host, port);
+        if !crate::policy::permits(&state.cfg, &host, port) {
+            host.zeroize();
+            return reply_error(&mut client, 0x02).await;
+        }
+    }
 
-    let mut prefs = StreamPrefs::new();
-    prefs.set_isolation(stream_token);
+    match command {
+        CMD_RESOLVE => return socks_resolve(&mut client, &state, host, stream_token).await,
+        CMD_RESOLVE_PTR => return socks_resolve_ptr(&mut client, &state, host, stream_token).await,
+        _ => {}
+    }
+
+    tracing::debug!("Routing {}:{} through Tor...", host, port);
 
-    let tor_stream_result = tor.connect_with_prefs((host.as_str(), port), &prefs).await;
+    let tor_stream_result = connect_tor(&state, &host, port, stream_token).await;
     host.zeroize(); 
 
     let tor_stream: DataStream = match tor_stream_result {
         Ok(stream) => stream,
         Err(e) => {
             tracing::warn!("Tor failed to route to target: {}", e);
-            let _ = reply_failure(&mut client).await;
+            let _ = reply_error(&mut client, e.socks_reply()).await;
             return Ok(());
         }
     };
@@ -265,6 +439,185 @@ where
     let _ = client.write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).await;
     let _ = client.flush().await; 
 
+    relay(client, tor_stream).await;
+    Ok(())
+}
+
+/// Tor RESOLVE: replies with the first address in the BND.ADDR field.
+async fn socks_resolve<R: Runtime, S>(
+    client: &mut S,
+    state: &ProxyState<R>,
+    mut host: String,
+    token: IsolationToken,
+) -> Result<()>
+where
+    S: AsyncWriteExt + Unpin,
+{
+    let result = resolve::lookup_host(state, &host, token).await;
+    host.zeroize();
+
+    let addr = match result {
+        Ok((addrs, _)) if !addrs.is_empty() => addrs[0],
+        Ok(_) | Err(ErrorKind::RemoteHostNotFound) => return reply_error(client, 0x04).await,
+        Err(kind) => {
+            tracing::warn!("Tor failed to resolve target: {}", kind);
+            return reply_error(client, 0x01).await;
+        }
+    };
+
+    let mut reply = vec![0x05, 0x00, 0x00];
+    match addr {
+        IpAddr::V4(v4) => {
+            reply.push(0x01);
+            reply.extend_from_slice(&v4.octets());
+        }
+        IpAddr::V6(v6) => {
+            reply.push(0x04);
+            reply.extend_from_slice(&v6.octets());
+        }
+    }
+    reply.extend_from_slice(&[0, 0]);
+
+    client.write_all(&reply).await?;
+    client.flush().await?;
+    Ok(())
+}
+
+/// Tor RESOLVE_PTR: replies with the first name as a domain-type BND.ADDR.
+async fn socks_resolve_ptr<R: Runtime, S>(
+    client: &mut S,
+    state: &ProxyState<R>,
+    mut host: String,
+    token: IsolationToken,
+) -> Result<()>
+where
+    S: AsyncWriteExt + Unpin,
+{
+    let parsed = host.parse::<IpAddr>();
+    host.zeroize();
+
+    let Ok(addr) = parsed else {
+        return reply_error(client, 0x08).await;
+    };
+
+    let mut name = match resolve::lookup_ptr(state, addr, token).await {
+        Ok((mut names, _)) if !names.is_empty() => names.swap_remove(0),
+        Ok(_) | Err(ErrorKind::RemoteHostNotFound) => return reply_error(client, 0x04).await,
+        Err(kind) => {
+            tracing::warn!("Tor failed to resolve address: {}", kind);
+            return reply_error(client, 0x01).await;
+        }
+    };
+
+    let len = name.len().min(255);
+    let mut reply = vec![0x05, 0x00, 0x00, 0x03, len as u8];
+    reply.extend_from_slice(&name.as_bytes()[..len]);
+    reply.extend_from_slice(&[0, 0]);
+    name.zeroize();
+
+    client.write_all(&reply).await?;
+    client.flush().await?;
+    reply.zeroize();
+    Ok(())
+}
+
+/// Why `connect_tor` failed: Tor itself, the chained proxy behind it, or a time limit
+#[derive(Debug)]
+pub(crate) enum ConnectError {
+    Tor(arti_client::Error),
+    Chain(TunnelError),
+    /// The named step ran past its time limit
+    Timeout(&'static str),
+}
+
+impl ConnectError {
+    /// SOCKS5 REP code to report to the client
+    pub fn socks_reply(&self) -> u8 {
+        match self {
+            ConnectError::Tor(e) => tor_error_reply(e.kind()),
+            ConnectError::Chain(e) => e.reply,
+            ConnectError::Timeout(_) => 0x06,
+        }
+    }
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectError::Tor(e) => e.fmt(f),
+            ConnectError::Chain(e) => write!(f, "chained proxy: {}", e),
+            ConnectError::Timeout(step) => write!(f, "{} timed out", step),
+        }
+    }
+}
+
+/// Opens a Tor stream on the given isolation token, through the matching
+/// CHAIN_RULES hop if there is one. Shared by every front-end so they all
+/// take the same connect path.
+pub(crate) async fn connect_tor<R: Runtime>(
+    state: &ProxyState<R>,
+    host: &str,
+    port: u16,
+    token: IsolationToken,
+) -> Result<DataStream, ConnectError> {
+    let mut prefs = StreamPrefs::new();
+    prefs.set_isolation(token);
+
+    let hop = state.chains.hop_for(host, port);
+    let onion = match hop {
+        Some(hop) => hop.proxy().addr.rsplit_once(':').is_some_and(|(h, _)| h.ends_with(".onion")),
+        None => host.ends_with(".onion"),
+    };
+
+    let Some(hop) = hop else {
+        return bound_onion(state, onion, state.tor.connect_with_prefs((host, port), &prefs)).await;
+    };
+
+    let mut stream = bound_onion(state, onion, state.tor.connect_with_prefs(hop.proxy().addr.as_str(), &prefs)).await?;
+
+    // Arti's connect timeout ends with the Tor leg; a hop that stalls mid-handshake is ours to bound
+    let limit = Duration::from_secs(state.cfg.tor_connect_timeout_secs);
+    match timeout(limit, hop.open(&mut stream, host, port)).await {
+        Ok(result) => result.map_err(ConnectError::Chain)?,
+        Err(_) => return Err(ConnectError::Timeout("chained proxy handshake")),
+    }
+    Ok(stream)
+}
+
+async fn bound_onion<R, F, T>(state: &ProxyState<R>, onion: bool, connect: F) -> Result<T, ConnectError>
+where
+    R: Runtime,
+    F: std::future::Future<Output = Result<T, arti_client::Error>>,
+{
+    if !onion {
+        return connect.await.map_err(ConnectError::Tor);
+    }
+    let limit = Duration::from_secs(state.cfg.onion_pow_max_secs);
+    bounded(&state.onion_connects, limit, connect).await
+}
+
+/// Caps an onion connect at `limit`, time spent waiting for a slot included.
+/// Dropping the connect on expiry also stops its PoW solver thread, so a
+/// solve cannot burn CPU indefinitely.
+async fn bounded<F, T>(slots: &Semaphore, limit: Duration, connect: F) -> Result<T, ConnectError>
+where
+    F: std::future::Future<Output = Result<T, arti_client::Error>>,
+{
+    let attempt = async {
+        let _slot = slots.acquire().await.expect("onion connect semaphore is never closed");
+        connect.await
+    };
+    match timeout(limit, attempt).await {
+        Ok(result) => result.map_err(ConnectError::Tor),
+        Err(_) => Err(ConnectError::Timeout("onion connect")),
+    }
+}
+
+/// Pumps bytes both ways between a client and its Tor stream until either side closes.
+pub(crate) async fn relay<S>(client: S, tor_stream: DataStream)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     let (cr, cw) = tokio::io::split(client);
     let (tr, tw) = tokio::io::split(tor_stream);
 
@@ -272,8 +625,6 @@ where
         zeroizing_copy(cr, tw),
         zeroizing_copy(tr, cw),
     );
-    
-    Ok(())
 }
 
 async fn zeroizing_copy<R, W>(mut reader: R, mut writer: W) -> Result<()>
@@ -297,7 +648,75 @@ where
 }
 
 async fn reply_failure<S: AsyncWriteExt + Unpin>(stream: &mut S) -> Result<()> {
-    let _ = stream.write_all(&[0x05, 0x01, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).await;
+    reply_error(stream, 0x01).await
+}
+
+/// SOCKS5 REP code for a failed Tor connect. Onion service failures use Tor's
+/// extended codes (prop304), so clients can tell a missing key from a dead service.
+fn tor_error_reply(kind: ErrorKind) -> u8 {
+    match kind {
+        ErrorKind::OnionServiceNotFound => 0xF0,
+        ErrorKind::OnionServiceProtocolViolation => 0xF1,
+        ErrorKind::OnionServiceNotRunning => 0xF2,
+        ErrorKind::OnionServiceConnectionFailed => 0xF3,
+        ErrorKind::OnionServiceMissingClientAuth => 0xF4,
+        ErrorKind::OnionServiceWrongClientAuth => 0xF5,
+        ErrorKind::OnionServiceAddressInvalid => 0xF6,
+        _ => 0x01,
+    }
+}
+
+/// Sends a SOCKS5 error reply with the given REP code.
+async fn reply_error<S: AsyncWriteExt + Unpin>(stream: &mut S, rep: u8) -> Result<()> {
+    let _ = stream.write_all(&[0x05, rep, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).await;
     let _ = stream.flush().await;
     Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::pending;
+
+    #[tokio::test]
+    async fn stalled_onion_connect_does_not_block_others() {
+        let slots = Semaphore::new(64);
+        let stalled = bounded(&slots, Duration::from_secs(60), pending::<Result<(), arti_client::Error>>());
+        let second = bounded(&slots, Duration::from_secs(60), async { Ok(()) });
+        tokio::pin!(stalled);
+
+        // `biased` polls the stalled connect first, so it holds its slot
+        let result = timeout(Duration::from_secs(5), async {
+            tokio::select! {
+                biased;
+                _ = &mut stalled => panic!("stalled connect finished"),
+                result = second => result,
+            }
+        })
+        .await
+        .expect("second onion connect waited on the stalled one");
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn queued_onion_connect_times_out() {
+        let slots = Semaphore::new(1);
+        let stalled = bounded(&slots, Duration::from_secs(60), pending::<Result<(), arti_client::Error>>());
+        let queued = bounded(&slots, Duration::from_millis(100), async { Ok(()) });
+        tokio::pin!(stalled);
+
+        let result = timeout(Duration::from_secs(5), async {
+            tokio::select! {
+                biased;
+                _ = &mut stalled => panic!("stalled connect finished"),
+                result = queued => result,
+            }
+        })
+        .await
+        .expect("queued onion connect ignored its time limit");
+        match result {
+            Err(e @ ConnectError::Timeout(_)) => assert_eq!(e.socks_reply(), 0x06),
+            _ => panic!("queued onion connect did not time out"),
+        }
+    }
 }
\ No newline at end of file
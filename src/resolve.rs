@@ -0,0 +1,201 @@
+// src/resolve.rs
+//
+// Tor name resolution shared by the DNS front-ends and SOCKS RESOLVE.
+// Answers are cached per isolation token, so a cached lookup made under one
+// identity is never served to another. NEWNYM flushes everything.
+// MapAddress rewrites apply first; automapped names never reach Tor and get
+// virtual addresses instead.
+
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use arti_client::isolation::IsolationToken;
+use arti_client::{ErrorKind, HasKind, StreamPrefs};
+use tor_rtcompat::Runtime;
+use zeroize::Zeroize;
+
+use crate::config::Config;
+use crate::proxy::ProxyState;
+
+/// Lifetime assumed for an answer before clamping (Tor does not relay upstream TTLs)
+const NOMINAL_TTL: u32 = 60;
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Query {
+    Host(String),
+    Ptr(IpAddr),
+}
+
+impl Drop for Query {
+    fn drop(&mut self) {
+        if let Query::Host(name) = self {
+            name.zeroize();
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Answer {
+    Addrs(Vec<IpAddr>),
+    Names(Vec<String>),
+    NotFound,
+}
+
+impl Drop for Answer {
+    fn drop(&mut self) {
+        if let Answer::Names(names) = self {
+            names.iter_mut().for_each(Zeroize::zeroize);
+        }
+    }
+}
+
+/// In-memory resolver cache. Only memory-locked when strict mode has run
+/// mlockall; otherwise entries can reach swap like any other heap data.
+pub struct DnsCache {
+    entries: Mutex<BTreeMap<(IsolationToken, Query), (Answer, Instant)>>,
+    max_entries: usize,
+    ttl: u32,
+}
+
+impl DnsCache {
+    pub fn new(cfg: &Config) -> Self {
+        if cfg.dns_cache_size > 0 && !cfg.strict_mode {
+            tracing::warn!("DNS cache is not memory-locked outside strict mode");
+        }
+
+        DnsCache {
+            entries: Mutex::new(BTreeMap::new()),
+            max_entries: cfg.dns_cache_size,
+            ttl: NOMINAL_TTL.clamp(cfg.dns_cache_min_ttl, cfg.dns_cache_max_ttl),
+        }
+    }
+
+    /// Cached answer and its remaining lifetime in seconds
+    fn get(&self, token: IsolationToken, query: &Query) -> Option<(Answer, u32)> {
+        let mut entries = self.entries.lock().unwrap();
+        let key = (token, query.clone());
+        let (answer, expires) = entries.get(&key)?;
+
+        let remaining = expires.saturating_duration_since(Instant::now()).as_secs() as u32;
+        if remaining == 0 {
+            entries.remove(&key);
+            return None;
+        }
+        Some((answer.clone(), remaining))
+    }
+
+    fn insert(&self, token: IsolationToken, query: Query, answer: Answer) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.len() >= self.max_entries {
+            entries.retain(|_, (_, expires)| *expires > now);
+        }
+        if entries.len() >= self.max_entries {
+            // Still full: drop whatever expires soonest
+            let soonest = entries.iter().min_by_key(|(_, (_, expires))| *expires).map(|(k, _)| k.clone());
+            if let Some(key) = soonest {
+                entries.remove(&key);
+            }
+        }
+
+        entries.insert((token, query), (answer, now + Duration::from_secs(self.ttl as u64)));
+    }
+
+    pub fn flush(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// TTL handed to clients for a fresh answer
+    pub fn ttl(&self) -> u32 {
+        self.ttl
+    }
+}
+
+/// Forward lookup through Tor. Returns the addresses and the TTL to report.
+pub async fn lookup_host<R: Runtime>(
+    state: &ProxyState<R>,
+    name: &str,
+    token: IsolationToken,
+) -> Result<(Vec<IpAddr>, u32), ErrorKind> {
+    // MapAddress applies to lookups too; the caller's token stays keyed on the original name
+    let mapped = state.rewrites.rewrite(name);
+    let name = mapped.as_deref().unwrap_or(name);
+
+    if state.automap.should_map(name) {
+        let (v4, v6) = state.automap.map(name);
+        return Ok((vec![IpAddr::V4(v4), IpAddr::V6(v6)], state.dns_cache.ttl()));
+    }
+
+    let query = Query::Host(name.to_string());
+
+    if let Some((answer, ttl)) = state.dns_cache.get(token, &query) {
+        return match answer {
+            Answer::Addrs(ref addrs) => Ok((addrs.clone(), ttl)),
+            _ => Err(ErrorKind::RemoteHostNotFound),
+        };
+    }
+
+    let mut prefs = StreamPrefs::new();
+    prefs.set_isolation(token);
+
+    match state.tor.resolve_with_prefs(name, &prefs).await {
+        Ok(addrs) => {
+            state.dns_cache.insert(token, query, Answer::Addrs(addrs.clone()));
+            Ok((addrs, state.dns_cache.ttl()))
+        }
+        Err(e) => Err(remember_failure(state, token, query, e.kind())),
+    }
+}
+
+/// Reverse lookup through Tor. Returns the names and the TTL to report.
+pub async fn lookup_ptr<R: Runtime>(
+    state: &ProxyState<R>,
+    addr: IpAddr,
+    token: IsolationToken,
+) -> Result<(Vec<String>, u32), ErrorKind> {
+    if state.automap.is_virtual(addr) {
+        return match state.automap.name_for(addr) {
+            Some(name) => Ok((vec![name], state.dns_cache.ttl())),
+            None => Err(ErrorKind::RemoteHostNotFound),
+        };
+    }
+
+    let query = Query::Ptr(addr);
+
+    if let Some((answer, ttl)) = state.dns_cache.get(token, &query) {
+        return match answer {
+            Answer::Names(ref names) => Ok((names.clone(), ttl)),
+            _ => Err(ErrorKind::RemoteHostNotFound),
+        };
+    }
+
+    let mut prefs = StreamPrefs::new();
+    prefs.set_isolation(token);
+
+    match state.tor.resolve_ptr_with_prefs(addr, &prefs).await {
+        Ok(names) => {
+            state.dns_cache.insert(token, query, Answer::Names(names.clone()));
+            Ok((names, state.dns_cache.ttl()))
+        }
+        Err(e) => Err(remember_failure(state, token, query, e.kind())),
+    }
+}
+
+/// Negative answers are cached; transient failures (timeouts, circuits) are not.
+fn remember_failure<R: Runtime>(state: &ProxyState<R>, token: IsolationToken, query: Query, kind: ErrorKind) -> ErrorKind {
+    if kind == ErrorKind::RemoteHostNotFound {
+        state.dns_cache.insert(token, query, Answer::NotFound);
+    }
+    kind
+}
@@ -0,0 +1,129 @@
+// src/router.rs
+//
+// SNI/ALPN dispatch for the single TLS port.
+// The ClientHello is inspected before the handshake completes, so each route
+// can present its own client-certificate policy and ALPN.
+
+use anyhow::Result;
+use std::sync::Arc;
+
+use tokio_rustls::rustls::server::ClientHello;
+use tokio_rustls::rustls::ServerConfig;
+
+use crate::config::{ClientAuth, Config, Service, TlsRoute};
+use crate::tls::{self, TlsMaterial};
+
+/// Where an accepted connection is sent after the handshake
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    Service(Service, ClientAuth),
+    /// No route matched; only reachable when a decoy is configured
+    Decoy,
+}
+
+struct RouteEntry {
+    route: TlsRoute,
+    config: Arc<ServerConfig>,
+    /// Variant that negotiates http/1.1, used when a decoy may answer and the client offers it
+    http11: Option<Arc<ServerConfig>>,
+}
+
+impl RouteEntry {
+    fn new(cfg: &Config, material: &TlsMaterial, route: TlsRoute) -> Result<Self> {
+        let config = tls::server_config(cfg, material, &route)?;
+
+        // Look like any HTTPS site to scanners that negotiate ALPN, without
+        // alerting clients that only offer protocols we do not speak.
+        let http11 = match (&cfg.decoy, &route.alpn, route.client_auth) {
+            (Some(_), None, ClientAuth::Optional) => {
+                let mut variant = config.clone();
+                variant.alpn_protocols = vec![b"http/1.1".to_vec()];
+                Some(Arc::new(variant))
+            }
+            _ => None,
+        };
+
+        Ok(RouteEntry { route, config: Arc::new(config), http11 })
+    }
+
+    fn config_for(&self, offered: &[&[u8]]) -> Arc<ServerConfig> {
+        match &self.http11 {
+            Some(variant) if offered.contains(&&b"http/1.1"[..]) => variant.clone(),
+            _ => self.config.clone(),
+        }
+    }
+}
+
+pub struct Router {
+    routes: Vec<RouteEntry>,
+    fallback: Option<RouteEntry>,
+}
+
+impl Router {
+    pub fn new(cfg: &Config, material: &TlsMaterial) -> Result<Self> {
+        let routes = cfg
+            .tls_routes
+            .iter()
+            .map(|r| RouteEntry::new(cfg, material, r.clone()))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Unmatched hellos look like a plain web server when a decoy exists
+        let fallback = match cfg.decoy {
+            Some(_) => {
+                let decoy_route = TlsRoute {
+                    service: Service::Socks,
+                    sni: None,
+                    alpn: None,
+                    client_auth: ClientAuth::Optional,
+                    client_ca: None,
+                };
+                Some(RouteEntry::new(cfg, material, decoy_route)?)
+            }
+            None => None,
+        };
+
+        for RouteEntry { route, .. } in &routes {
+            tracing::info!(
+                "TLS route: {:?} sni={} alpn={} client_auth={:?}",
+                route.service,
+                route.sni.as_deref().unwrap_or("*"),
+                route.alpn.as_deref().unwrap_or("*"),
+                route.client_auth
+            );
+        }
+
+        Ok(Router { routes, fallback })
+    }
+
+    /// First route whose SNI and ALPN constraints both match wins.
+    pub fn select(&self, hello: &ClientHello<'_>) -> Option<(Target, Arc<ServerConfig>)> {
+        let sni = hello.server_name().map(str::to_ascii_lowercase);
+        let offered: Vec<&[u8]> = hello.alpn().map(|a| a.collect()).unwrap_or_default();
+
+        for entry in &self.routes {
+            let route = &entry.route;
+            let sni_ok = match (&route.sni, &sni) {
+                (None, _) => true,
+                (Some(want), Some(got)) => sni_matches(want, got),
+                (Some(_), None) => false,
+            };
+            let alpn_ok = match &route.alpn {
+                None => true,
+                Some(want) => offered.contains(&want.as_bytes()),
+            };
+
+            if sni_ok && alpn_ok {
+                return Some((Target::Service(route.service, route.client_auth), entry.config_for(&offered)));
+            }
+        }
+
+        self.fallback.as_ref().map(|entry| (Target::Decoy, entry.config_for(&offered)))
+    }
+}
+
+fn sni_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => name.len() > suffix.len() && name.ends_with(suffix) && name[..name.len() - suffix.len()].ends_with('.'),
+        None => pattern == name,
+    }
+}
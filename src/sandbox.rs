@@ -0,0 +1,399 @@
+// src/sandbox.rs
+//
+// `torrust run -- <cmd>`: starts a program in fresh user, network and mount
+// namespaces. The new network namespace holds nothing but a loopback
+// interface, so the program has no route anywhere. Its only way out is a
+// SOCKS5 port and a DNS port on 127.0.0.1. Those listeners are created inside
+// the namespace and passed back here over a socket pair, then served by the
+// usual Tor paths. /run, /var/run and /tmp are masked, since the Unix sockets
+// there (D-Bus, X11, a ControlPort) would otherwise reach past the namespace.
+
+use anyhow::Result;
+use std::ffi::OsString;
+use std::sync::Arc;
+
+use tor_rtcompat::Runtime;
+
+use crate::proxy::ProxyState;
+
+/// SOCKS5 port inside the namespace (Tor's conventional port, torsocks' default)
+#[cfg(target_os = "linux")]
+const SOCKS_PORT: u16 = 9050;
+
+#[cfg(target_os = "linux")]
+const DNS_PORT: u16 = 53;
+
+/// Hidden behind empty tmpfs mounts: filesystem Unix sockets live here (resolvers,
+/// D-Bus, X11, a local Tor ControlPort) and would reach past the network namespace
+#[cfg(target_os = "linux")]
+const MASKED_DIRS: [&std::ffi::CStr; 3] = [c"/run", c"/var/run", c"/tmp"];
+
+/// Runs `command` in the namespace until it exits. Returns its exit code.
+#[cfg(target_os = "linux")]
+pub async fn run<R: Runtime>(state: Arc<ProxyState<R>>, command: Vec<OsString>) -> Result<i32> {
+    use anyhow::Context;
+    use std::ffi::CString;
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixStream;
+    use tokio::net::{TcpListener, UdpSocket};
+    use tokio::process::Command;
+
+    let (program, args) = command.split_first().context("No command given")?;
+
+    // Everything the child needs is prepared here: after fork only raw syscalls are safe
+    let resolv_conf = state.cfg.tor_state_dir.join("resolv.conf");
+    std::fs::write(&resolv_conf, "nameserver 127.0.0.1\noptions edns0\n")
+        .context("Cannot write namespace resolv.conf")?;
+    let _resolv_conf_guard = RemoveOnDrop(&resolv_conf);
+
+    // /etc/resolv.conf is often a link into /run, which is masked before the bind
+    let resolv_target = std::fs::canonicalize("/etc/resolv.conf").unwrap_or_else(|_| "/etc/resolv.conf".into());
+    let resolv_dirs = resolv_target
+        .ancestors()
+        .skip(1)
+        .filter(|dir| dir.parent().is_some())
+        .map(|dir| CString::new(dir.as_os_str().as_bytes()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+    let setup = Setup {
+        uid_map: format!("{uid} {uid} 1").into_bytes(),
+        gid_map: format!("{gid} {gid} 1").into_bytes(),
+        resolv_source: CString::new(resolv_conf.as_os_str().as_bytes())?,
+        resolv_target: CString::new(resolv_target.as_os_str().as_bytes())?,
+        resolv_dirs: resolv_dirs.into_iter().rev().collect(),
+    };
+
+    let (ours, theirs) = UnixStream::pair().context("Cannot create socket pair")?;
+    let channel = theirs.as_raw_fd();
+
+    let mut child = Command::new(program);
+    child
+        .args(args)
+        .env("ALL_PROXY", format!("socks5h://127.0.0.1:{SOCKS_PORT}"))
+        .env("all_proxy", format!("socks5h://127.0.0.1:{SOCKS_PORT}"))
+        .kill_on_drop(true);
+
+    unsafe {
+        child.pre_exec(move || enter_namespace(&setup, channel));
+    }
+
+    let mut child = child
+        .spawn()
+        .with_context(|| format!("Cannot start {:?} in a network namespace", program))?;
+    drop(theirs);
+
+    // The listeners were sent before exec, so they are already queued
+    let [socks, dns_tcp, dns_udp] = receive_fds(&ours).context("Namespace listeners not received")?;
+    drop(ours);
+
+    let socks = unsafe { std::net::TcpListener::from_raw_fd(socks) };
+    let dns_tcp = unsafe { std::net::TcpListener::from_raw_fd(dns_tcp) };
+    let dns_udp = unsafe { std::net::UdpSocket::from_raw_fd(dns_udp) };
+    socks.set_nonblocking(true)?;
+    dns_tcp.set_nonblocking(true)?;
+    dns_udp.set_nonblocking(true)?;
+
+    let socks = TcpListener::from_std(socks)?;
+    let dns_tcp = TcpListener::from_std(dns_tcp)?;
+    let dns_udp = UdpSocket::from_std(dns_udp)?;
+
+    tracing::info!(
+        "Running {:?} in a private network namespace (SOCKS 127.0.0.1:{}, DNS 127.0.0.1:{})",
+        program,
+        SOCKS_PORT,
+        DNS_PORT
+    );
+
+    {
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = crate::dns::serve_dns(state, dns_udp, dns_tcp).await {
+                tracing::error!("Namespace DNS terminated: {e}");
+            }
+        });
+    }
+
+    tokio::spawn(async move {
+        loop {
+            let socket = match socks.accept().await {
+                Ok((socket, _)) => socket,
+                Err(e) => {
+                    tracing::warn!("Namespace SOCKS accept failed: {}", e);
+                    continue;
+                }
+            };
+            let state = state.clone();
+            tokio::spawn(async move {
+                if let Err(e) = crate::proxy::handle_socks_connection(socket, state, None).await {
+                    tracing::debug!("Namespace SOCKS session ended: {:#}", e);
+                }
+            });
+        }
+    });
+
+    // The terminal delivers Ctrl-C to the child too; outlive it so it can clean up
+    let status = loop {
+        tokio::select! {
+            status = child.wait() => break status.context("Lost track of the child process")?,
+            _ = tokio::signal::ctrl_c() => tracing::debug!("Interrupt received, waiting for the child to exit"),
+        }
+    };
+
+    Ok(match status.code() {
+        Some(code) => code,
+        None => {
+            use std::os::unix::process::ExitStatusExt;
+            128 + status.signal().unwrap_or(0)
+        }
+    })
+}
+
+/// Removes a file when dropped, so early returns do not leave it behind.
+#[cfg(target_os = "linux")]
+struct RemoveOnDrop<'a>(&'a std::path::Path);
+
+#[cfg(target_os = "linux")]
+impl Drop for RemoveOnDrop<'_> {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(self.0);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub async fn run<R: Runtime>(_state: Arc<ProxyState<R>>, _command: Vec<OsString>) -> Result<i32> {
+    anyhow::bail!("`torrust run` requires Linux namespaces")
+}
+
+#[cfg(target_os = "linux")]
+struct Setup {
+    uid_map: Vec<u8>,
+    gid_map: Vec<u8>,
+    resolv_source: std::ffi::CString,
+    resolv_target: std::ffi::CString,
+    /// Parents of `resolv_target`, outermost first, to recreate inside a masked dir
+    resolv_dirs: Vec<std::ffi::CString>,
+}
+
+/// Runs in the forked child before exec. No allocation, no locks.
+#[cfg(target_os = "linux")]
+fn enter_namespace(setup: &Setup, channel: libc::c_int) -> std::io::Result<()> {
+    use std::ptr::null;
+
+    unsafe {
+        // A non-dumpable process cannot write its own /proc/self/*_map
+        check(libc::prctl(libc::PR_SET_DUMPABLE, 1, 0, 0, 0))?;
+        check(libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET | libc::CLONE_NEWNS))?;
+
+        write_file(c"/proc/self/setgroups", b"deny")?;
+        write_file(c"/proc/self/uid_map", &setup.uid_map)?;
+        write_file(c"/proc/self/gid_map", &setup.gid_map)?;
+
+        // Mount changes stay inside the namespace
+        check(libc::mount(null(), c"/".as_ptr(), null(), libc::MS_REC | libc::MS_PRIVATE, null()))?;
+
+        // Held open across the masking, since the state dir may sit under a masked dir
+        let source = libc::open(setup.resolv_source.as_ptr(), libc::O_PATH | libc::O_CLOEXEC);
+        check(source)?;
+
+        for dir in MASKED_DIRS {
+            let flags = libc::MS_NOSUID | libc::MS_NODEV;
+            if libc::mount(c"tmpfs".as_ptr(), dir.as_ptr(), c"tmpfs".as_ptr(), flags, null()) != 0 {
+                let err = std::io::Error::last_os_error();
+                if err.raw_os_error() != Some(libc::ENOENT) {
+                    return Err(err);
+                }
+            }
+        }
+
+        // Recreate the resolv.conf target if masking hid it, then bind ours over it
+        for dir in &setup.resolv_dirs {
+            if libc::mkdir(dir.as_ptr(), 0o755) != 0 && std::io::Error::last_os_error().raw_os_error() != Some(libc::EEXIST) {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        let fd = libc::open(setup.resolv_target.as_ptr(), libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL | libc::O_CLOEXEC, 0o644);
+        if fd >= 0 {
+            libc::close(fd);
+        } else if std::io::Error::last_os_error().raw_os_error() != Some(libc::EEXIST) {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut path = [0u8; 32];
+        let bound = check(libc::mount(
+            fd_path(source, &mut path),
+            setup.resolv_target.as_ptr(),
+            null(),
+            libc::MS_BIND,
+            null(),
+        ));
+        libc::close(source);
+        bound?;
+
+        loopback_up()?;
+
+        let fds = [
+            bind_loopback(libc::SOCK_STREAM, SOCKS_PORT)?,
+            bind_loopback(libc::SOCK_STREAM, DNS_PORT)?,
+            bind_loopback(libc::SOCK_DGRAM, DNS_PORT)?,
+        ];
+        send_fds(channel, &fds)?;
+        for fd in fds {
+            libc::close(fd);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn check(ret: libc::c_int) -> std::io::Result<()> {
+    if ret < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// `/proc/self/fd/<fd>` as a C string in `buf`, without allocating.
+#[cfg(target_os = "linux")]
+fn fd_path(fd: libc::c_int, buf: &mut [u8; 32]) -> *const libc::c_char {
+    const PREFIX: &[u8] = b"/proc/self/fd/";
+    buf[..PREFIX.len()].copy_from_slice(PREFIX);
+
+    let mut digits = [0u8; 10];
+    let (mut n, mut len) = (fd as u32, 0);
+    loop {
+        digits[len] = b'0' + (n % 10) as u8;
+        len += 1;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    for i in 0..len {
+        buf[PREFIX.len() + i] = digits[len - 1 - i];
+    }
+    buf[PREFIX.len() + len] = 0;
+    buf.as_ptr().cast()
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn write_file(path: &std::ffi::CStr, contents: &[u8]) -> std::io::Result<()> {
+    let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+    check(fd)?;
+    let written = libc::write(fd, contents.as_ptr().cast(), contents.len());
+    libc::close(fd);
+    if written != contents.len() as isize {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// A new network namespace starts with `lo` down.
+#[cfg(target_os = "linux")]
+unsafe fn loopback_up() -> std::io::Result<()> {
+    let sock = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+    check(sock)?;
+
+    let mut req: libc::ifreq = std::mem::zeroed();
+    req.ifr_name[0] = b'l' as libc::c_char;
+    req.ifr_name[1] = b'o' as libc::c_char;
+
+    let result = check(libc::ioctl(sock, libc::SIOCGIFFLAGS as _, &mut req)).and_then(|()| {
+        req.ifr_ifru.ifru_flags |= (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short;
+        check(libc::ioctl(sock, libc::SIOCSIFFLAGS as _, &mut req))
+    });
+    libc::close(sock);
+    result
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn bind_loopback(kind: libc::c_int, port: u16) -> std::io::Result<libc::c_int> {
+    let fd = libc::socket(libc::AF_INET, kind | libc::SOCK_CLOEXEC, 0);
+    check(fd)?;
+
+    let mut addr: libc::sockaddr_in = std::mem::zeroed();
+    addr.sin_family = libc::AF_INET as libc::sa_family_t;
+    addr.sin_port = port.to_be();
+    addr.sin_addr.s_addr = u32::from(std::net::Ipv4Addr::LOCALHOST).to_be();
+
+    let bound = check(libc::bind(
+        fd,
+        &addr as *const _ as *const libc::sockaddr,
+        std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+    ));
+    let result = bound.and_then(|()| match kind {
+        libc::SOCK_STREAM => check(libc::listen(fd, 128)),
+        _ => Ok(()),
+    });
+
+    if let Err(e) = result {
+        libc::close(fd);
+        return Err(e);
+    }
+    Ok(fd)
+}
+
+/// Room for the three listener descriptors in one SCM_RIGHTS message
+#[cfg(target_os = "linux")]
+type CmsgBuffer = [u64; 8];
+
+#[cfg(target_os = "linux")]
+unsafe fn send_fds(channel: libc::c_int, fds: &[libc::c_int; 3]) -> std::io::Result<()> {
+    let mut byte = [0u8; 1];
+    let mut iov = libc::iovec { iov_base: byte.as_mut_ptr().cast(), iov_len: 1 };
+    let mut control: CmsgBuffer = [0; 8];
+    let data_len = std::mem::size_of_val(fds) as u32;
+
+    let mut msg: libc::msghdr = std::mem::zeroed();
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = libc::CMSG_SPACE(data_len) as _;
+
+    let cmsg = libc::CMSG_FIRSTHDR(&msg);
+    (*cmsg).cmsg_level = libc::SOL_SOCKET;
+    (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+    (*cmsg).cmsg_len = libc::CMSG_LEN(data_len) as _;
+    std::ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg).cast(), fds.len());
+
+    if libc::sendmsg(channel, &msg, libc::MSG_NOSIGNAL) < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn receive_fds(channel: &std::os::unix::net::UnixStream) -> std::io::Result<[libc::c_int; 3]> {
+    use std::os::fd::AsRawFd;
+
+    let mut fds = [-1; 3];
+    unsafe {
+        let mut byte = [0u8; 1];
+        let mut iov = libc::iovec { iov_base: byte.as_mut_ptr().cast(), iov_len: 1 };
+        let mut control: CmsgBuffer = [0; 8];
+
+        let mut msg: libc::msghdr = std::mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = std::mem::size_of::<CmsgBuffer>() as _;
+
+        if libc::recvmsg(channel.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        let expected = libc::CMSG_LEN(std::mem::size_of_val(&fds) as u32) as usize;
+        if cmsg.is_null()
+            || (*cmsg).cmsg_level != libc::SOL_SOCKET
+            || (*cmsg).cmsg_type != libc::SCM_RIGHTS
+            || (*cmsg).cmsg_len as usize != expected
+        {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "unexpected control message"));
+        }
+        std::ptr::copy_nonoverlapping(libc::CMSG_DATA(cmsg).cast(), fds.as_mut_ptr(), fds.len());
+    }
+    Ok(fds)
+}
@@ -0,0 +1,71 @@
+// src/testnet.rs
+//
+// Private Tor networks for integration tests: a chutney-style network on one
+// machine, with its own directory authorities and fallback caches and no
+// route to the real Tor network. Such a network is whatever its operator
+// says it is, so these settings need `--testing-network`, which strict mode
+// refuses outright.
+//
+// Every relay of a local network shares 127.0.0.1, so the usual rule against
+// two relays from one subnet on a circuit is switched off, and local
+// addresses become reachable as destinations (the test servers).
+
+use anyhow::Result;
+
+use arti_client::config::dir::FallbackDirBuilder;
+use arti_client::config::TorClientConfigBuilder;
+
+use crate::config::Config;
+
+/// Whether any private-network setting is present.
+pub fn requested(cfg: &Config) -> bool {
+    !cfg.tor_dir_authorities.is_empty() || !cfg.tor_fallback_dirs.is_empty() || !cfg.tor_net_params.is_empty()
+}
+
+/// Points the client at the configured private network. Fails unless the
+/// operator passed `--testing-network`.
+pub fn configure(cfg: &Config, testing_network: bool, tor_cfg: &mut TorClientConfigBuilder) -> Result<()> {
+    if !testing_network {
+        if requested(cfg) {
+            anyhow::bail!("TOR_DIR_AUTHORITIES, TOR_FALLBACK_DIRS and TOR_NET_PARAMS need --testing-network");
+        }
+        return Ok(());
+    }
+    if cfg.tor_dir_authorities.is_empty() {
+        anyhow::bail!("--testing-network needs TOR_DIR_AUTHORITIES and TOR_FALLBACK_DIRS");
+    }
+
+    tor_cfg
+        .tor_network()
+        .authorities()
+        .set_v3idents(cfg.tor_dir_authorities.clone());
+
+    let fallbacks = cfg
+        .tor_fallback_dirs
+        .iter()
+        .map(|dir| {
+            let mut builder = FallbackDirBuilder::new();
+            builder.rsa_identity(dir.rsa_identity).ed_identity(dir.ed_identity);
+            builder.orports().extend(dir.orports.iter().copied());
+            builder
+        })
+        .collect();
+    tor_cfg.tor_network().set_fallback_caches(fallbacks);
+
+    tor_cfg.override_net_params().extend(cfg.tor_net_params.iter().cloned());
+
+    // A longer prefix than the address itself: no two relays count as one subnet
+    tor_cfg
+        .path_rules()
+        .ipv4_subnet_family_prefix(33)
+        .ipv6_subnet_family_prefix(129);
+    tor_cfg.address_filter().allow_local_addrs(true);
+
+    tracing::warn!(
+        "TESTING NETWORK: {} private authorities, {} fallbacks, {} parameter overrides; not the Tor network",
+        cfg.tor_dir_authorities.len(),
+        cfg.tor_fallback_dirs.len(),
+        cfg.tor_net_params.len()
+    );
+    Ok(())
+}
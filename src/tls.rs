@@ -0,0 +1,279 @@
+// src/tls.rs
+//
+// TLS policy for the mTLS front-end.
+// Builds the rustls ServerConfig from the operator-selected crypto provider,
+// protocol floor, cipher suites and key exchange groups.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rustls::crypto::{aws_lc_rs, ring, CryptoProvider, SupportedKxGroup};
+use rustls::sign::{CertifiedKey, SingleCertAndKey};
+use rustls::{InconsistentKeys, SupportedCipherSuite, SupportedProtocolVersion};
+use rustls_pki_types::CertificateDer;
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio::time::{sleep, Duration};
+use tracing::{error, info, warn};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::config::{ClientAuth, Config, TlsRoute};
+use crate::identity::{self, load_certs, SecretKey, ServerIdentity};
+
+/// Builds the crypto provider described by the TLS policy settings.
+/// Suites and groups keep the order given by the operator (server preference order).
+fn crypto_provider(cfg: &Config) -> Result<CryptoProvider> {
+    let (base, all_suites, all_groups): (CryptoProvider, &[SupportedCipherSuite], &[&'static dyn SupportedKxGroup]) =
+        match cfg.tls_crypto_provider.as_str() {
+            "ring" => (ring::default_provider(), ring::ALL_CIPHER_SUITES, ring::ALL_KX_GROUPS),
+            "aws-lc-rs" => (aws_lc_rs::default_provider(), aws_lc_rs::ALL_CIPHER_SUITES, aws_lc_rs::ALL_KX_GROUPS),
+            other => anyhow::bail!("Unknown TLS crypto provider '{}' (expected ring or aws-lc-rs)", other),
+        };
+
+    let mut provider = base;
+
+    if !cfg.tls_cipher_suites.is_empty() {
+        provider.cipher_suites = cfg
+            .tls_cipher_suites
+            .iter()
+            .map(|name| {
+                all_suites
+                    .iter()
+                    .find(|s| s.suite().as_str() == Some(name.as_str()))
+                    .copied()
+                    .with_context(|| format!(
+                        "Cipher suite '{}' not offered by the {} provider",
+                        name, cfg.tls_crypto_provider
+                    ))
+            })
+            .collect::<Result<_>>()?;
+    }
+
+    if !cfg.tls_kx_groups.is_empty() {
+        provider.kx_groups = cfg
+            .tls_kx_groups
+            .iter()
+            .map(|name| {
+                all_groups
+                    .iter()
+                    .find(|g| g.name().as_str() == Some(name.as_str()))
+                    .copied()
+                    .with_context(|| format!(
+                        "Key exchange group '{}' not offered by the {} provider \
+                         (post-quantum groups require aws-lc-rs)",
+                        name, cfg.tls_crypto_provider
+                    ))
+            })
+            .collect::<Result<_>>()?;
+    }
+
+    Ok(provider)
+}
+
+static TLS13_ONLY: &[&SupportedProtocolVersion] = &[&rustls::version::TLS13];
+
+fn protocol_versions(cfg: &Config) -> Result<&'static [&'static SupportedProtocolVersion]> {
+    match cfg.tls_min_version.as_str() {
+        "1.2" => Ok(rustls::ALL_VERSIONS),
+        "1.3" => Ok(TLS13_ONLY),
+        other => anyhow::bail!("Invalid TLS minimum version '{}' (expected 1.2 or 1.3)", other),
+    }
+}
+
+/// Binds the server key to its leaf certificate, rejecting a key that belongs to another cert.
+/// rustls gets its own copy; the caller's `SecretKey` is wiped when it drops.
+fn certified_key(
+    chain: Vec<CertificateDer<'static>>,
+    key: &SecretKey,
+    provider: &CryptoProvider,
+) -> Result<CertifiedKey> {
+    CertifiedKey::from_der(chain, key.der().clone_key(), provider).map_err(|e| match e {
+        rustls::Error::InconsistentKeys(InconsistentKeys::KeyMismatch) => {
+            anyhow::anyhow!("Private key does not match the server leaf certificate")
+        }
+        other => anyhow::anyhow!("Unusable server private key: {}", other),
+    })
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Checks the validity window of every certificate in a bundle.
+/// Expired or not-yet-valid certs are errors; certs inside the warning window are logged.
+fn check_validity(certs_vec: &[CertificateDer<'_>], what: &str, warn_days: u64) -> Result<()> {
+    let now = unix_now();
+    let warn_secs = i64::try_from(warn_days.saturating_mul(86_400)).unwrap_or(i64::MAX);
+
+    for (i, der) in certs_vec.iter().enumerate() {
+        let (_, cert) = X509Certificate::from_der(der)
+            .map_err(|e| anyhow::anyhow!("{} certificate #{} is not valid X.509: {}", what, i, e))?;
+        let subject = cert.subject().to_string();
+        let validity = cert.validity();
+
+        if validity.not_before.timestamp() > now {
+            anyhow::bail!("{} certificate '{}' is not valid until {}", what, subject, validity.not_before);
+        }
+        if validity.not_after.timestamp() < now {
+            anyhow::bail!("{} certificate '{}' expired on {}", what, subject, validity.not_after);
+        }
+        if validity.not_after.timestamp() - now < warn_secs {
+            warn!(
+                "{} certificate '{}' expires in {} day(s) ({})",
+                what,
+                subject,
+                (validity.not_after.timestamp() - now) / 86_400,
+                validity.not_after
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The server leaf must be usable for TLS server authentication.
+fn check_server_eku(leaf: &CertificateDer<'_>) -> Result<()> {
+    let (_, cert) = X509Certificate::from_der(leaf)
+        .map_err(|e| anyhow::anyhow!("Server certificate is not valid X.509: {}", e))?;
+
+    match cert.extended_key_usage() {
+        Ok(Some(eku)) if eku.value.server_auth || eku.value.any => Ok(()),
+        Ok(Some(_)) => anyhow::bail!(
+            "Server certificate '{}' lacks the serverAuth extended key usage",
+            cert.subject()
+        ),
+        Ok(None) => anyhow::bail!(
+            "Server certificate '{}' has no extended key usage extension (serverAuth required)",
+            cert.subject()
+        ),
+        Err(e) => anyhow::bail!("Server certificate has a malformed EKU extension: {}", e),
+    }
+}
+
+/// Common name of an authenticated peer's leaf certificate, used to key
+/// per-client policy.
+pub fn peer_common_name(certs: &[CertificateDer<'_>]) -> Option<String> {
+    let (_, cert) = X509Certificate::from_der(certs.first()?).ok()?;
+    let cn = cert.subject().iter_common_name().next()?.as_str().ok()?.to_string();
+    Some(cn)
+}
+
+/// Validated server identity and client trust anchors, loaded once at startup.
+#[derive(Clone)]
+pub struct TlsMaterial {
+    pub identity: Arc<CertifiedKey>,
+    pub client_ca: Vec<CertificateDer<'static>>,
+    /// Trust anchors of routes that override the default client CA
+    pub route_cas: HashMap<PathBuf, Vec<CertificateDer<'static>>>,
+}
+
+/// Validates all TLS material before Tor is bootstrapped, so operators get
+/// a precise error instead of a late handshake failure.
+pub fn preflight(cfg: &Config) -> Result<TlsMaterial> {
+    let provider = crypto_provider(cfg)?;
+    let versions = protocol_versions(cfg)?;
+
+    // `key` wipes itself on every return below, including early `?` errors
+    let ServerIdentity { chain, key } = identity::load(cfg)?;
+    let client_ca = load_certs(&cfg.tls_client_ca_path, "client CA bundle")?;
+
+    check_server_eku(&chain[0])?;
+    check_validity(&chain, "Server", cfg.tls_expiry_warn_days)?;
+    check_validity(&client_ca, "Client CA", cfg.tls_expiry_warn_days)?;
+
+    let mut route_cas = HashMap::new();
+    for path in cfg.tls_routes.iter().filter_map(|r| r.client_ca.as_ref()) {
+        let bundle = load_certs(path, "route client CA bundle")?;
+        check_validity(&bundle, "Route client CA", cfg.tls_expiry_warn_days)?;
+        route_cas.insert(path.clone(), bundle);
+    }
+
+    let identity = Arc::new(certified_key(chain, &key, &provider)?);
+    drop(key);
+
+    log_policy(cfg, &provider, versions);
+    info!("TLS preflight passed");
+    Ok(TlsMaterial { identity, client_ca, route_cas })
+}
+
+/// Re-checks certificate expiry while running, since proxies outlive their certs.
+pub fn spawn_expiry_monitor(material: TlsMaterial, warn_days: u64, check_hours: u64) {
+    tokio::spawn(async move {
+        let interval = Duration::from_secs(check_hours.max(1).saturating_mul(3600));
+        loop {
+            sleep(interval).await;
+
+            let result = check_validity(&material.identity.cert, "Server", warn_days)
+                .and_then(|_| check_validity(&material.client_ca, "Client CA", warn_days))
+                .and_then(|_| {
+                    material
+                        .route_cas
+                        .values()
+                        .try_for_each(|ca| check_validity(ca, "Route client CA", warn_days))
+                });
+
+            if let Err(e) = result {
+                error!("TLS expiry check failed: {:#}", e);
+            }
+        }
+    });
+}
+
+/// Builds the mTLS server configuration for one route of the TLS listener.
+pub fn server_config(cfg: &Config, material: &TlsMaterial, route: &TlsRoute) -> Result<ServerConfig> {
+    let provider = Arc::new(crypto_provider(cfg)?);
+    let versions = protocol_versions(cfg)?;
+
+    // 1. Load the CA Certificate for mTLS verification
+    let ca_certs = match &route.client_ca {
+        Some(path) => material.route_cas.get(path).context("Route CA bundle was not preflighted")?,
+        None => &material.client_ca,
+    };
+    let mut roots = RootCertStore::empty();
+    for cert in ca_certs.iter().cloned() {
+        roots.add(cert).context("Failed to add CA cert to trust roots")?;
+    }
+    let mut verifier = WebPkiClientVerifier::builder_with_provider(roots.into(), provider.clone());
+    if route.client_auth == ClientAuth::Optional {
+        // Presented certs are still verified; absent ones are routed to the decoy.
+        verifier = verifier.allow_unauthenticated();
+    }
+    let client_verifier = verifier.build().context("Failed to build client verifier")?;
+
+    // 2. Enforce mTLS under the configured policy
+    let mut server_config = ServerConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(versions)
+        .context("TLS policy has no usable cipher suites for the selected versions")?
+        .with_client_cert_verifier(client_verifier) // <-- The Cryptographic Bouncer
+        .with_cert_resolver(Arc::new(SingleCertAndKey::from(material.identity.clone())));
+
+    if let Some(alpn) = &route.alpn {
+        server_config.alpn_protocols = vec![alpn.as_bytes().to_vec()];
+    }
+
+    Ok(server_config)
+}
+
+fn log_policy(cfg: &Config, provider: &CryptoProvider, versions: &[&SupportedProtocolVersion]) {
+    let suites: Vec<_> = provider
+        .cipher_suites
+        .iter()
+        .filter(|s| versions.iter().any(|v| v.version == s.version().version))
+        .map(|s| format!("{:?}", s.suite()))
+        .collect();
+    let groups: Vec<_> = provider.kx_groups.iter().map(|g| format!("{:?}", g.name())).collect();
+    let versions: Vec<_> = versions.iter().map(|v| format!("{:?}", v.version)).collect();
+
+    info!(
+        "TLS policy: provider={}, versions=[{}], suites=[{}], kx=[{}]",
+        cfg.tls_crypto_provider,
+        versions.join(","),
+        suites.join(","),
+        groups.join(",")
+    );
+}
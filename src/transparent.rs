@@ -0,0 +1,159 @@
+// src/transparent.rs
+//
+// Transparent TCP proxy (Tor's TransPort) for iptables/nftables REDIRECT.
+// The original destination is read back from conntrack, mapped through the
+// virtual address table, and sent down the same policy and Tor connect path
+// as SOCKS. Plaintext by nature, so only ever bound to a local interface.
+
+use anyhow::{Context, Result};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::net::{TcpListener, TcpStream};
+use tor_rtcompat::Runtime;
+use zeroize::Zeroize;
+
+use crate::proxy::{connect_tor, relay, ProxyState};
+
+pub async fn start_trans_server<R: Runtime>(state: Arc<ProxyState<R>>, bind_addr: SocketAddr) -> Result<()> {
+    let listener = TcpListener::bind(bind_addr).await.context("Failed to bind transparent listener")?;
+
+    tracing::info!("Transparent proxy listening on {}", bind_addr);
+
+    loop {
+        let (socket, peer_addr) = listener.accept().await?;
+
+        if let Err(e) = socket.set_nodelay(true) {
+            tracing::warn!("Failed to set TCP_NODELAY: {}", e);
+        }
+
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_transparent(socket, state).await {
+                tracing::debug!("Transparent session from {} ended: {:#}", peer_addr, e);
+            }
+        });
+    }
+}
+
+async fn handle_transparent<R: Runtime>(socket: TcpStream, state: Arc<ProxyState<R>>) -> Result<()> {
+    let destination = original_destination(&socket).context("No original destination (not redirected?)")?;
+
+    // A direct connection to the listener would loop back into it
+    if destination == socket.local_addr()? {
+        anyhow::bail!("Connection was not redirected");
+    }
+
+    let port = destination.port();
+    let mut host = destination.ip().to_string();
+
+    if !state.automap.rewrite(&mut host) {
+        tracing::warn!("Transparent target is an unmapped virtual address");
+        host.zeroize();
+        return Ok(());
+    }
+
+    if state.filter.is_blocked(None, &host) {
+        tracing::debug!("Blocked by domain policy: {}", host);
+        host.zeroize();
+        return Ok(());
+    }
+
+    // Isolation follows the name the client asked for, not the MapAddress target
+    let token = state.isolation_token(None, &host);
+
+    if let Some(target) = state.rewrites.rewrite(&host) {
+        tracing::debug!("MapAddress: {} -> {}", host, target);
+        host.zeroize();
+        host = target;
+
+        // A rewrite must not lead around the blocklists
+        if state.filter.is_blocked(None, &host) {
+            tracing::debug!("Blocked by domain policy (MapAddress target): {}", host);
+            host.zeroize();
+            return Ok(());
+        }
+    }
+
+    if !crate::policy::permits(&state.cfg, &host, port) {
+        host.zeroize();
+        return Ok(());
+    }
+
+    tracing::debug!("Routing {}:{} through Tor (transparent)...", host, port);
+
+    let tor_stream_result = connect_tor(&state, &host, port, token).await;
+    host.zeroize();
+
+    let tor_stream = match tor_stream_result {
+        Ok(stream) => stream,
+        Err(e) => {
+            tracing::warn!("Tor failed to route to target: {}", e);
+            return Ok(());
+        }
+    };
+
+    relay(socket, tor_stream).await;
+    Ok(())
+}
+
+/// Destination before NAT redirection (SO_ORIGINAL_DST / IP6T_SO_ORIGINAL_DST).
+#[cfg(target_os = "linux")]
+fn original_destination(socket: &TcpStream) -> std::io::Result<SocketAddr> {
+    use std::mem::{size_of, zeroed};
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::os::fd::AsRawFd;
+
+    let fd = socket.as_raw_fd();
+    let ipv4 = match socket.local_addr()? {
+        SocketAddr::V4(_) => true,
+        SocketAddr::V6(local) => local.ip().to_ipv4_mapped().is_some(),
+    };
+
+    unsafe {
+        if ipv4 {
+            let mut addr: libc::sockaddr_in = zeroed();
+            let mut len = size_of::<libc::sockaddr_in>() as libc::socklen_t;
+            let ret = libc::getsockopt(
+                fd,
+                libc::SOL_IP,
+                libc::SO_ORIGINAL_DST,
+                &mut addr as *mut _ as *mut libc::c_void,
+                &mut len,
+            );
+            if ret != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(SocketAddr::from((
+                Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+                u16::from_be(addr.sin_port),
+            )))
+        } else {
+            let mut addr: libc::sockaddr_in6 = zeroed();
+            let mut len = size_of::<libc::sockaddr_in6>() as libc::socklen_t;
+            let ret = libc::getsockopt(
+                fd,
+                libc::SOL_IPV6,
+                libc::IP6T_SO_ORIGINAL_DST,
+                &mut addr as *mut _ as *mut libc::c_void,
+                &mut len,
+            );
+            if ret != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(SocketAddr::from((
+                Ipv6Addr::from(addr.sin6_addr.s6_addr),
+                u16::from_be(addr.sin6_port),
+            )))
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn original_destination(_socket: &TcpStream) -> std::io::Result<SocketAddr> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "transparent proxying requires Linux netfilter",
+    ))
+}
@@ -0,0 +1,49 @@
+// src/tuning.rs
+//
+// Circuit and path tuning handed to Arti: vanguards, channel padding, how
+// long circuits take new streams, which ports get circuits ahead of demand or
+// need Stable relays, and stream timeouts. Values are validated in config and
+// default to Arti's own, so an unset variable changes nothing.
+
+use std::time::Duration;
+
+use arti_client::config::TorClientConfigBuilder;
+use tor_config::{ExplicitOrAuto, PaddingLevel};
+use tor_guardmgr::VanguardMode;
+
+use crate::config::{Config, Padding, Vanguards};
+
+/// Applies the TOR_* tuning settings to the Tor configuration.
+pub fn configure(cfg: &Config, tor_cfg: &mut TorClientConfigBuilder) {
+    // Unset leaves the builder alone, so Arti's own default stands
+    if let Some(vanguards) = cfg.tor_vanguards {
+        let mode = match vanguards {
+            Vanguards::Lite => VanguardMode::Lite,
+            Vanguards::Full => VanguardMode::Full,
+        };
+        tor_cfg.vanguards().mode(ExplicitOrAuto::Explicit(mode));
+    }
+
+    if let Some(padding) = cfg.tor_padding {
+        let level = match padding {
+            Padding::Normal => PaddingLevel::Normal,
+            Padding::Reduced => PaddingLevel::Reduced,
+        };
+        tor_cfg.channel().padding(level);
+    }
+
+    tor_cfg
+        .circuit_timing()
+        .max_dirtiness(Duration::from_secs(cfg.tor_max_dirtiness_secs))
+        // Each failed introduction retries with more PoW effort; this caps the escalation
+        .hs_intro_rend_attempts(cfg.onion_intro_attempts);
+
+    *tor_cfg.preemptive_circuits().initial_predicted_ports() = cfg.tor_preemptive_ports.clone();
+    *tor_cfg.path_rules().long_lived_ports() = cfg.tor_long_lived_ports.clone();
+
+    tor_cfg
+        .stream_timeouts()
+        .connect_timeout(Duration::from_secs(cfg.tor_connect_timeout_secs))
+        .resolve_timeout(Duration::from_secs(cfg.tor_resolve_timeout_secs))
+        .resolve_ptr_timeout(Duration::from_secs(cfg.tor_resolve_timeout_secs));
+}
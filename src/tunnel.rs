@@ -0,0 +1,328 @@
+// src/tunnel.rs
+//
+// Client side of the HTTP CONNECT and SOCKS5 handshakes, shared by the
+// upstream proxy (before Tor) and proxy chains (after Tor). Failures carry the
+// SOCKS5 reply code that best describes them, so a chain failure reaches the
+// application as something more useful than "general failure".
+
+use anyhow::{Context, Result};
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+
+use data_encoding::BASE64;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use zeroize::Zeroizing;
+
+use crate::config::ProxyKind;
+use crate::identity;
+
+/// Longest CONNECT response head we accept
+const MAX_RESPONSE_HEAD: usize = 8192;
+
+pub struct Credentials {
+    user: Zeroizing<String>,
+    pass: Zeroizing<String>,
+}
+
+impl Credentials {
+    /// Reads `user:password` for a `kind` proxy from an fd or file, if either is given.
+    pub fn read(kind: ProxyKind, fd: Option<i32>, file: Option<&Path>) -> Result<Option<Self>> {
+        let Some(raw) = identity::read_passphrase_from(fd, file)? else { return Ok(None) };
+        let (user, pass) = raw.split_once(':').context("Proxy credentials must be user:password")?;
+
+        // SOCKS5 (RFC 1929) length limits; HTTP has none worth enforcing
+        if kind == ProxyKind::Socks5 && (user.is_empty() || user.len() > 255 || pass.len() > 255) {
+            anyhow::bail!("SOCKS5 credentials must be 1-255 bytes each");
+        }
+        Ok(Some(Credentials {
+            user: Zeroizing::new(user.to_string()),
+            pass: Zeroizing::new(pass.to_string()),
+        }))
+    }
+}
+
+#[derive(Debug)]
+pub struct TunnelError {
+    /// SOCKS5 REP code to hand the application
+    pub reply: u8,
+    reason: String,
+}
+
+impl TunnelError {
+    fn new(reply: u8, reason: impl Into<String>) -> Self {
+        TunnelError { reply, reason: reason.into() }
+    }
+}
+
+impl fmt::Display for TunnelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.reason)
+    }
+}
+
+impl std::error::Error for TunnelError {}
+
+impl From<io::Error> for TunnelError {
+    fn from(e: io::Error) -> Self {
+        TunnelError::new(0x01, format!("proxy connection failed: {}", e))
+    }
+}
+
+/// Asks the proxy on `stream` for a tunnel to `host:port`.
+pub async fn open<S>(
+    stream: &mut S,
+    kind: ProxyKind,
+    host: &str,
+    port: u16,
+    credentials: Option<&Credentials>,
+) -> Result<(), TunnelError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    match kind {
+        ProxyKind::HttpConnect => http_connect(stream, host, port, credentials).await,
+        ProxyKind::Socks5 => socks5_connect(stream, host, port, credentials).await,
+    }
+}
+
+async fn http_connect<S>(stream: &mut S, host: &str, port: u16, credentials: Option<&Credentials>) -> Result<(), TunnelError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let target = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(v6)) => format!("[{}]:{}", v6, port),
+        _ => format!("{}:{}", host, port),
+    };
+
+    let mut request = Zeroizing::new(format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target));
+    if let Some(creds) = credentials {
+        let token = Zeroizing::new(BASE64.encode(format!("{}:{}", creds.user.as_str(), creds.pass.as_str()).as_bytes()));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token.as_str()));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+
+    // Byte at a time: anything past the head belongs to the tunnelled protocol
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_RESPONSE_HEAD {
+            return Err(TunnelError::new(0x01, "proxy response head too large"));
+        }
+        let mut byte = [0u8; 1];
+        stream.read_exact(&mut byte).await?;
+        head.push(byte[0]);
+    }
+
+    let status_line = head.split(|b| *b == b'\r').next().unwrap_or_default();
+    let status_line = String::from_utf8_lossy(status_line);
+    let code = status_line.split_whitespace().nth(1).and_then(|c| c.parse::<u16>().ok()).unwrap_or(0);
+
+    let reply = match code {
+        200..=299 => return Ok(()),
+        403 | 407 => 0x02,
+        404 => 0x04,
+        502 | 503 => 0x05,
+        504 => 0x06,
+        _ => 0x01,
+    };
+    Err(TunnelError::new(reply, format!("proxy answered '{}'", status_line)))
+}
+
+async fn socks5_connect<S>(stream: &mut S, host: &str, port: u16, credentials: Option<&Credentials>) -> Result<(), TunnelError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let method = if credentials.is_some() { 0x02 } else { 0x00 };
+    stream.write_all(&[0x05, 0x01, method]).await?;
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply != [0x05, method] {
+        return Err(TunnelError::new(0x01, "proxy refused the SOCKS5 authentication method"));
+    }
+
+    if let Some(creds) = credentials {
+        let mut auth = Zeroizing::new(vec![0x01, creds.user.len() as u8]);
+        auth.extend_from_slice(creds.user.as_bytes());
+        auth.push(creds.pass.len() as u8);
+        auth.extend_from_slice(creds.pass.as_bytes());
+        stream.write_all(&auth).await?;
+
+        stream.read_exact(&mut reply).await?;
+        if reply[1] != 0x00 {
+            return Err(TunnelError::new(0x02, "proxy rejected the SOCKS5 credentials"));
+        }
+    }
+
+    // Names go to the proxy unresolved, so no lookup happens on our side
+    let mut request = vec![0x05, 0x01, 0x00];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(v4)) => {
+            request.push(0x01);
+            request.extend_from_slice(&v4.octets());
+        }
+        Ok(IpAddr::V6(v6)) => {
+            request.push(0x04);
+            request.extend_from_slice(&v6.octets());
+        }
+        Err(_) if host.len() <= 255 => {
+            request.push(0x03);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+        Err(_) => return Err(TunnelError::new(0x01, "destination name too long for SOCKS5")),
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+    stream.flush().await?;
+
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+    if header[1] != 0x00 {
+        // The proxy's own REP code already means what we want to say
+        let reply = if (0x01..=0x08).contains(&header[1]) { header[1] } else { 0x01 };
+        return Err(TunnelError::new(reply, format!("proxy SOCKS5 reply {:#04x}", header[1])));
+    }
+
+    // Skip BND.ADDR and BND.PORT
+    let addr_len = match header[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize
+        }
+        other => return Err(TunnelError::new(0x01, format!("proxy SOCKS5 address type {:#04x}", other))),
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+    use tokio::net::{TcpListener, TcpStream};
+    use tor_rtcompat::{NetStreamProvider, PreferredRuntime};
+
+    /// Sent by the stand-in proxies right after a successful handshake
+    const TUNNELLED: &[u8] = b"tunnelled bytes";
+
+    fn credentials(user: &str, pass: &str) -> Credentials {
+        Credentials {
+            user: Zeroizing::new(user.to_string()),
+            pass: Zeroizing::new(pass.to_string()),
+        }
+    }
+
+    /// Runs `tunnel::open` to example.com:443 against a stand-in proxy and
+    /// returns whatever the tunnel carries after the handshake.
+    async fn open_via<F, Fut>(proxy: F, kind: ProxyKind, creds: &Credentials) -> Result<Vec<u8>, TunnelError>
+    where
+        F: FnOnce(TcpStream) -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = ()> + Send,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            proxy(socket).await;
+        });
+
+        let mut stream = PreferredRuntime::current().unwrap().connect(&addr).await.unwrap();
+        open(&mut stream, kind, "example.com", 443, Some(creds)).await?;
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).await?;
+        Ok(rest)
+    }
+
+    /// HTTP CONNECT stand-in: answers `status`, or 407 unless alice:secret is presented.
+    async fn http_proxy(mut socket: TcpStream, status: &str) {
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(socket.read_u8().await.unwrap());
+        }
+        let head = String::from_utf8(head).unwrap();
+        assert!(head.starts_with("CONNECT example.com:443 HTTP/1.1\r\n"));
+
+        let expected = format!("Proxy-Authorization: Basic {}\r\n", BASE64.encode(b"alice:secret"));
+        let status = if head.contains(&expected) { status } else { "407 Proxy Authentication Required" };
+        let mut response = format!("HTTP/1.1 {}\r\n\r\n", status).into_bytes();
+        if status.starts_with('2') {
+            response.extend_from_slice(TUNNELLED);
+        }
+        socket.write_all(&response).await.unwrap();
+    }
+
+    /// SOCKS5 stand-in: replies `rep`, or fails auth unless alice:secret is presented.
+    async fn socks5_proxy(mut socket: TcpStream, rep: u8) {
+        let mut greeting = [0u8; 3];
+        socket.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(greeting, [0x05, 0x01, 0x02]);
+        socket.write_all(&[0x05, 0x02]).await.unwrap();
+
+        assert_eq!(socket.read_u8().await.unwrap(), 0x01);
+        let mut user = vec![0u8; socket.read_u8().await.unwrap() as usize];
+        socket.read_exact(&mut user).await.unwrap();
+        let mut pass = vec![0u8; socket.read_u8().await.unwrap() as usize];
+        socket.read_exact(&mut pass).await.unwrap();
+        let authorized = user == b"alice" && pass == b"secret";
+        socket.write_all(&[0x01, if authorized { 0x00 } else { 0x01 }]).await.unwrap();
+        if !authorized {
+            return;
+        }
+
+        let mut request = [0u8; 5];
+        socket.read_exact(&mut request).await.unwrap();
+        assert_eq!(request, [0x05, 0x01, 0x00, 0x03, 11]);
+        let mut target = [0u8; 13];
+        socket.read_exact(&mut target).await.unwrap();
+        assert_eq!(&target, b"example.com\x01\xbb");
+
+        let mut reply = vec![0x05, rep, 0x00, 0x01, 0, 0, 0, 0, 0, 0];
+        if rep == 0x00 {
+            reply.extend_from_slice(TUNNELLED);
+        }
+        socket.write_all(&reply).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn http_connect_handshake() {
+        let good = credentials("alice", "secret");
+        let rest = open_via(|s| async move { http_proxy(s, "200 Connection established").await }, ProxyKind::HttpConnect, &good)
+            .await
+            .unwrap();
+        assert_eq!(rest, TUNNELLED);
+
+        let bad = credentials("alice", "wrong");
+        let err = open_via(|s| async move { http_proxy(s, "200 Connection established").await }, ProxyKind::HttpConnect, &bad)
+            .await
+            .unwrap_err();
+        assert_eq!(err.reply, 0x02);
+
+        let err = open_via(|s| async move { http_proxy(s, "502 Bad Gateway").await }, ProxyKind::HttpConnect, &good)
+            .await
+            .unwrap_err();
+        assert_eq!(err.reply, 0x05);
+    }
+
+    #[tokio::test]
+    async fn socks5_handshake() {
+        let good = credentials("alice", "secret");
+        let rest = open_via(|s| socks5_proxy(s, 0x00), ProxyKind::Socks5, &good).await.unwrap();
+        assert_eq!(rest, TUNNELLED);
+
+        let bad = credentials("alice", "wrong");
+        let err = open_via(|s| socks5_proxy(s, 0x00), ProxyKind::Socks5, &bad).await.unwrap_err();
+        assert_eq!(err.reply, 0x02);
+
+        let err = open_via(|s| socks5_proxy(s, 0x04), ProxyKind::Socks5, &good).await.unwrap_err();
+        assert_eq!(err.reply, 0x04);
+    }
+}
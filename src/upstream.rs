@@ -0,0 +1,97 @@
+// src/upstream.rs
+//
+// Upstream proxy for hosts whose only way out is a corporate proxy (C Tor's
+// HTTPSProxy / Socks5Proxy). Arti's TCP provider is replaced, so every
+// connection it opens to a relay or bridge is tunnelled through the proxy,
+// with no helper binary to spawn. Loopback targets (unmanaged pluggable
+// transports) are still dialled directly. The tunnel runs over the runtime's
+// own stream type, so TLS and the rest of Arti see an ordinary TCP stream.
+
+use anyhow::{Context, Result};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tor_rtcompat::{CompoundRuntime, NetStreamProvider, PreferredRuntime, RuntimeSubstExt};
+
+use crate::config::{Config, ProxyKind};
+use crate::tunnel::{self, Credentials};
+
+/// The runtime handed to Arti: tokio, with the TCP provider swapped out
+pub type TorRuntime = CompoundRuntime<
+    PreferredRuntime,
+    PreferredRuntime,
+    PreferredRuntime,
+    UpstreamTcp,
+    PreferredRuntime,
+    PreferredRuntime,
+    PreferredRuntime,
+>;
+
+struct Upstream {
+    kind: ProxyKind,
+    addr: SocketAddr,
+    credentials: Option<Credentials>,
+}
+
+#[derive(Clone)]
+pub struct UpstreamTcp {
+    inner: PreferredRuntime,
+    proxy: Option<Arc<Upstream>>,
+}
+
+/// Builds the runtime for the Tor client. Without UPSTREAM_PROXY it connects directly.
+pub async fn runtime(cfg: &Config) -> Result<TorRuntime> {
+    let inner = PreferredRuntime::current().context("No async runtime")?;
+
+    let proxy = match &cfg.upstream_proxy {
+        Some(proxy) => {
+            // The proxy's own name is resolved once, locally; relay addresses never are
+            let addr = tokio::net::lookup_host(&proxy.addr)
+                .await
+                .ok()
+                .and_then(|mut addrs| addrs.next())
+                .with_context(|| format!("Cannot resolve upstream proxy {}", proxy.addr))?;
+
+            let credentials = Credentials::read(
+                proxy.kind,
+                cfg.upstream_proxy_credentials_fd,
+                cfg.upstream_proxy_credentials_file.as_deref(),
+            )
+            .context("Invalid upstream proxy credentials")?;
+
+            tracing::info!("Reaching the Tor network through {:?} proxy {}", proxy.kind, addr);
+            Some(Arc::new(Upstream { kind: proxy.kind, addr, credentials }))
+        }
+        None => None,
+    };
+
+    Ok(inner.with_tcp_provider(UpstreamTcp { inner: inner.clone(), proxy }))
+}
+
+#[async_trait]
+impl NetStreamProvider for UpstreamTcp {
+    type Stream = <PreferredRuntime as NetStreamProvider>::Stream;
+    type Listener = <PreferredRuntime as NetStreamProvider>::Listener;
+
+    async fn connect(&self, addr: &SocketAddr) -> io::Result<Self::Stream> {
+        let Some(proxy) = self.proxy.as_ref().filter(|_| !addr.ip().is_loopback()) else {
+            return self.inner.connect(addr).await;
+        };
+
+        let mut stream = self.inner.connect(&proxy.addr).await?;
+        let ip = addr.ip().to_string();
+        match tunnel::open(&mut stream, proxy.kind, &ip, addr.port(), proxy.credentials.as_ref()).await {
+            Ok(()) => Ok(stream),
+            Err(e) => {
+                tracing::debug!("Upstream proxy refused tunnel to {}: {}", addr, e);
+                Err(io::Error::other(e))
+            }
+        }
+    }
+
+    async fn listen(&self, addr: &SocketAddr) -> io::Result<Self::Listener> {
+        self.inner.listen(addr).await
+    }
+}